@@ -311,8 +311,9 @@ function runCmd(command, args, options = {}) {
       env: options.env,
     });
 
-    let stdout = "";
-    let stderr = "";
+    // Decoded once at the end so multibyte characters split across chunks survive
+    const stdoutChunks = [];
+    const stderrChunks = [];
     let timedOut = false;
 
     const timer = setTimeout(() => {
@@ -321,18 +322,16 @@ function runCmd(command, args, options = {}) {
       setTimeout(() => child.kill("SIGKILL"), 1500);
     }, timeoutMs);
 
-    child.stdout.on("data", (chunk) => {
-      stdout += chunk.toString();
-    });
-    child.stderr.on("data", (chunk) => {
-      stderr += chunk.toString();
-    });
+    child.stdout.on("data", (chunk) => stdoutChunks.push(chunk));
+    child.stderr.on("data", (chunk) => stderrChunks.push(chunk));
     child.on("error", (spawnErr) => {
       clearTimeout(timer);
       reject(new JsonRpcError(-32603, `Failed to start command '${command}'`, { cause: spawnErr.message }));
     });
     child.on("close", (code) => {
       clearTimeout(timer);
+      const stdout = Buffer.concat(stdoutChunks).toString("utf8");
+      const stderr = Buffer.concat(stderrChunks).toString("utf8");
       if (timedOut) {
         reject(new JsonRpcError(-32603, `Command timed out: ${command}`, { timeout_ms: timeoutMs, stderr, stdout }));
         return;
@@ -570,6 +569,41 @@ function extractMetrics(stderrText) {
   return metrics;
 }
 
+function metricsFromManifest(manifest, stderrText) {
+  const [repo] = manifest.repositories;
+  const { stats } = manifest;
+  return {
+    ...extractMetrics(stderrText),
+    total_files: stats.total_files,
+    rendered_files: stats.rendered_files,
+    skipped_files: stats.skipped_files,
+    rendered_bytes: stats.rendered_bytes,
+    rendered_lines: stats.rendered_lines,
+    skipped_by_reason: stats.skipped_by_reason,
+    languages: stats.languages,
+    head_commit: repo?.head_commit,
+    ref: repo?.ref,
+  };
+}
+
+// Run easycopy with `--manifest` and take the metrics from it. Binaries that
+// predate `--manifest` are run again without it and their stderr is scraped.
+async function runWithManifest(binaryPath, cmdArgs, timeoutMs) {
+  const dir = mkdtempSync(join(tmpdir(), "easycopy-manifest-"));
+  const manifestPath = join(dir, "manifest.json");
+  try {
+    const run = await runCmd(binaryPath, [...cmdArgs, "--manifest", manifestPath], { timeoutMs });
+    const manifest = JSON.parse(readFileSync(manifestPath, "utf8"));
+    return { run, metrics: metricsFromManifest(manifest, run.stderr) };
+  } catch (err) {
+    if (!String(err?.data?.stderr ?? "").includes("--manifest")) throw err;
+    const run = await runCmd(binaryPath, cmdArgs, { timeoutMs });
+    return { run, metrics: extractMetrics(run.stderr) };
+  } finally {
+    rmSync(dir, { recursive: true, force: true });
+  }
+}
+
 function extractCxmlFromHtml(html) {
   const marker = "<textarea id=\"llm-text\" readonly>";
   const endMarker = "</textarea>";
@@ -796,7 +830,7 @@ async function toolRenderRepo(args) {
 
   const outputPath = renderRepoOutputPath(args);
   const cmdArgs = buildEasycopyArgs(args, outputPath);
-  const run = await runCmd(binary.path, cmdArgs, { timeoutMs: args.timeout_ms ?? 15 * 60 * 1000 });
+  const { run, metrics } = await runWithManifest(binary.path, cmdArgs, args.timeout_ms ?? 15 * 60 * 1000);
 
   const htmlBuffer = readFileSync(outputPath);
   const htmlText = htmlBuffer.toString("utf8");
@@ -814,7 +848,7 @@ async function toolRenderRepo(args) {
     output_size_bytes: htmlBuffer.byteLength,
     output_sha256: sha256Bytes(htmlBuffer),
     easycopy: binary,
-    metrics: extractMetrics(run.stderr),
+    metrics,
     stderr_tail: run.stderr.split("\n").filter(Boolean).slice(-12),
     stdout_tail: run.stdout.split("\n").filter(Boolean).slice(-12),
     inline_html: includeInline && htmlBuffer.byteLength <= limit ? htmlText : null,
@@ -827,13 +861,27 @@ async function toolRenderRepo(args) {
 async function toolGetCxml(args) {
   validateGetCxmlArgs(args);
   const binary = await resolveEasycopyBinary();
+  const timeoutMs = args.timeout_ms ?? 15 * 60 * 1000;
 
-  const outputPath = resolve(process.cwd(), `easycopy-cxml-${Date.now()}.html`);
-  const cmdArgs = buildEasycopyArgs(args, outputPath);
-  const run = await runCmd(binary.path, cmdArgs, { timeoutMs: args.timeout_ms ?? 15 * 60 * 1000 });
+  // Newer binaries print the CXML document to stdout. Older ones only write
+  // HTML, so fall back to reading it back out of the page.
+  let run;
+  let metrics;
+  let cxml;
+  try {
+    ({ run, metrics } = await runWithManifest(binary.path, [...buildEasycopyArgs(args, "-"), "--format", "cxml"], timeoutMs));
+    cxml = run.stdout;
+  } catch (err) {
+    if (!String(err?.data?.stderr ?? "").includes("--format")) throw err;
 
-  const html = readFileSync(outputPath, "utf8");
-  const cxml = extractCxmlFromHtml(html);
+    const outputPath = resolve(process.cwd(), `easycopy-cxml-${Date.now()}.html`);
+    ({ run, metrics } = await runWithManifest(binary.path, buildEasycopyArgs(args, outputPath), timeoutMs));
+    try {
+      cxml = extractCxmlFromHtml(readFileSync(outputPath, "utf8"));
+    } finally {
+      unlinkSync(outputPath);
+    }
+  }
   const cxmlBuf = Buffer.from(cxml, "utf8");
 
   const data = {
@@ -845,9 +893,8 @@ async function toolGetCxml(args) {
     cxml_size_bytes: cxmlBuf.byteLength,
     cxml_sha256: sha256Bytes(cxmlBuf),
     easycopy: binary,
-    metrics: extractMetrics(run.stderr),
+    metrics,
     stderr_tail: run.stderr.split("\n").filter(Boolean).slice(-12),
-    stdout_tail: run.stdout.split("\n").filter(Boolean).slice(-12),
   };
 
   return makeTextAndStructured(data);
@@ -0,0 +1,258 @@
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+/// Archive formats that can be rendered like a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+/// File name suffixes for each format, longest first
+const SUFFIXES: &[(&str, ArchiveKind)] = &[
+    (".tar.gz", ArchiveKind::TarGz),
+    (".tgz", ArchiveKind::TarGz),
+    (".tar", ArchiveKind::Tar),
+    (".zip", ArchiveKind::Zip),
+];
+
+/// Detect an archive by its file name
+pub fn archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    SUFFIXES
+        .iter()
+        .find(|(suffix, _)| name.ends_with(suffix))
+        .map(|(_, kind)| *kind)
+}
+
+/// Name to render an archive under: its file name without the archive suffix
+pub fn archive_name(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let lower = name.to_lowercase();
+    let stem = SUFFIXES
+        .iter()
+        .find(|(suffix, _)| lower.ends_with(suffix))
+        .map(|(suffix, _)| &name[..name.len() - suffix.len()])
+        .unwrap_or(&name);
+
+    if stem.is_empty() {
+        "archive".to_string()
+    } else {
+        stem.to_string()
+    }
+}
+
+/// An entry read from an archive
+struct Entry {
+    path: PathBuf, // relative, already checked for traversal
+    is_dir: bool,
+}
+
+/// Unpack an archive into `dest`. A single top-level folder wrapping every
+/// entry, as in most release archives, is stripped. Entries with absolute
+/// paths or `..` components abort the extraction; links are skipped.
+pub fn extract(archive: &Path, dest: &Path) -> Result<()> {
+    let kind = archive_kind(archive)
+        .with_context(|| format!("Unsupported archive format: {}", archive.display()))?;
+
+    // First pass only reads names, to find a wrapping folder
+    let mut entries = Vec::new();
+    for_each_entry(archive, kind, &mut |entry, _| {
+        entries.push(entry);
+        Ok(())
+    })?;
+    let strip = wrapping_folder(&entries);
+
+    fs::create_dir_all(dest)
+        .with_context(|| format!("Failed to create {}", dest.display()))?;
+    for_each_entry(archive, kind, &mut |entry, reader| {
+        let rel = match &strip {
+            Some(folder) => entry.path.strip_prefix(folder).unwrap_or(&entry.path),
+            None => &entry.path,
+        };
+        if rel.as_os_str().is_empty() {
+            return Ok(());
+        }
+
+        let target = dest.join(rel);
+        if entry.is_dir {
+            fs::create_dir_all(&target)?;
+        } else {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = File::create(&target)
+                .with_context(|| format!("Failed to create {}", target.display()))?;
+            io::copy(reader, &mut file)
+                .with_context(|| format!("Failed to extract {}", rel.display()))?;
+        }
+        Ok(())
+    })
+}
+
+/// Call `f` with every file and directory in the archive
+fn for_each_entry(
+    archive: &Path,
+    kind: ArchiveKind,
+    f: &mut dyn FnMut(Entry, &mut dyn Read) -> Result<()>,
+) -> Result<()> {
+    let file = File::open(archive)
+        .with_context(|| format!("Failed to open archive {}", archive.display()))?;
+
+    match kind {
+        ArchiveKind::Tar | ArchiveKind::TarGz => {
+            let reader: Box<dyn Read> = match kind {
+                ArchiveKind::TarGz => Box::new(GzDecoder::new(file)),
+                _ => Box::new(file),
+            };
+            let mut tar = tar::Archive::new(reader);
+            for entry in tar.entries().context("Failed to read tar archive")? {
+                let mut entry = entry.context("Failed to read tar entry")?;
+                let name = entry.path()?.to_path_buf();
+                let entry_type = entry.header().entry_type();
+                let is_dir = entry_type.is_dir();
+                if !is_dir && !entry_type.is_file() {
+                    if entry_type.is_symlink() || entry_type.is_hard_link() {
+                        eprintln!("⚠️  Skipping link in archive: {}", name.display());
+                    }
+                    continue;
+                }
+                let Some(path) = checked_path(&name)? else {
+                    continue;
+                };
+                f(Entry { path, is_dir }, &mut entry)?;
+            }
+        }
+        ArchiveKind::Zip => {
+            let mut zip = zip::ZipArchive::new(file).context("Failed to read zip archive")?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i).context("Failed to read zip entry")?;
+                let name = PathBuf::from(entry.name());
+                // Symlinks are stored as files whose mode says otherwise
+                if entry.unix_mode().map(|mode| mode & 0o170000 == 0o120000).unwrap_or(false) {
+                    eprintln!("⚠️  Skipping link in archive: {}", name.display());
+                    continue;
+                }
+                let is_dir = entry.is_dir();
+                let Some(path) = checked_path(&name)? else {
+                    continue;
+                };
+                f(Entry { path, is_dir }, &mut entry)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Normalize an entry name, refusing any that would land outside the
+/// extraction directory. Returns None for entries naming the root itself.
+fn checked_path(name: &Path) -> Result<Option<PathBuf>> {
+    let mut path = PathBuf::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                anyhow::bail!("Refusing to extract archive entry outside the target directory: {}", name.display());
+            }
+        }
+    }
+    Ok((!path.as_os_str().is_empty()).then_some(path))
+}
+
+/// The folder every entry lives under, if there is exactly one and it is
+/// not the only thing in the archive
+fn wrapping_folder(entries: &[Entry]) -> Option<PathBuf> {
+    let first = entries.first()?.path.components().next()?;
+    let mut nested = false;
+    for entry in entries {
+        let mut components = entry.path.components();
+        if components.next() != Some(first) {
+            return None;
+        }
+        match components.next() {
+            Some(_) => nested = true,
+            // A file at the top level is not a wrapper
+            None if !entry.is_dir => return None,
+            None => {}
+        }
+    }
+    nested.then(|| PathBuf::from(first.as_os_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    /// Write a .tar.gz containing (name, content) files, with raw names so
+    /// traversal entries can be built
+    fn write_tar_gz(path: &Path, files: &[(&str, &str)]) {
+        let encoder = GzEncoder::new(File::create(path).unwrap(), Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            let bytes = name.as_bytes();
+            header.as_old_mut().name[..bytes.len()].copy_from_slice(bytes);
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn test_extract_tar_gz_strips_wrapping_folder() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("project-1.0.tar.gz");
+        write_tar_gz(&archive, &[("project-1.0/README.md", "readme"), ("project-1.0/src/lib.rs", "lib")]);
+
+        assert_eq!(archive_kind(&archive), Some(ArchiveKind::TarGz));
+        assert_eq!(archive_name(&archive), "project-1.0");
+
+        let dest = temp_dir.path().join("out");
+        extract(&archive, &dest).unwrap();
+        assert_eq!(fs::read_to_string(dest.join("README.md")).unwrap(), "readme");
+        assert_eq!(fs::read_to_string(dest.join("src/lib.rs")).unwrap(), "lib");
+    }
+
+    #[test]
+    fn test_extract_zip_without_wrapping_folder() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("drop.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("a/one.txt", options).unwrap();
+        zip.write_all(b"one").unwrap();
+        zip.start_file("b.txt", options).unwrap();
+        zip.write_all(b"two").unwrap();
+        zip.finish().unwrap();
+
+        let dest = temp_dir.path().join("out");
+        extract(&archive, &dest).unwrap();
+        assert_eq!(fs::read_to_string(dest.join("a/one.txt")).unwrap(), "one");
+        assert_eq!(fs::read_to_string(dest.join("b.txt")).unwrap(), "two");
+    }
+
+    #[test]
+    fn test_extract_refuses_path_traversal() {
+        let temp_dir = TempDir::new().unwrap();
+        let archive = temp_dir.path().join("evil.tar.gz");
+        write_tar_gz(&archive, &[("ok.txt", "fine"), ("../escaped.txt", "gotcha")]);
+
+        let dest = temp_dir.path().join("out");
+        let err = extract(&archive, &dest).unwrap_err();
+        assert!(err.to_string().contains("outside the target directory"));
+        assert!(!temp_dir.path().join("escaped.txt").exists());
+    }
+}
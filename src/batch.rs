@@ -0,0 +1,149 @@
+use crate::html_builder;
+use crate::utils;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Page names taken by the batch itself
+const RESERVED_NAMES: &[&str] = &["index"];
+
+/// A page written by `easycopy batch`
+#[derive(Debug, Clone)]
+pub struct BatchPage {
+    pub name: String,
+    pub file_name: String, // relative to the output directory
+    pub head_commit: String,
+    pub rendered_files: usize,
+    pub total_files: usize,
+    pub html_size: u64,
+}
+
+/// Outcome of one source of a batch
+#[derive(Debug, Clone)]
+pub struct BatchEntry {
+    pub source: String,
+    pub result: Result<BatchPage, String>,
+}
+
+/// Read the sources of a batch from a file, or stdin for `None` and `-`
+pub fn read_source_list(path: Option<&Path>) -> Result<Vec<String>> {
+    let text = utils::read_text_or_stdin(path.unwrap_or(Path::new("-")))
+        .context("Failed to read source list")?;
+    Ok(parse_source_list(&text))
+}
+
+/// One source per line; blank lines and `#` comments are skipped
+pub fn parse_source_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Render every source with `render_page` into `out_dir`, keep going past
+/// failures, then write index.html and report them. `render_page` gets the
+/// source and the page names used so far (see `page_file_name`).
+pub fn render_all(
+    sources: &[String],
+    out_dir: &Path,
+    mut render_page: impl FnMut(&str, &mut Vec<String>) -> Result<BatchPage>,
+) -> Result<()> {
+    if sources.is_empty() {
+        anyhow::bail!("No sources to render");
+    }
+    std::fs::create_dir_all(out_dir)
+        .with_context(|| format!("Failed to create output directory: {}", out_dir.display()))?;
+
+    let mut used_names: Vec<String> = RESERVED_NAMES.iter().map(|name| name.to_string()).collect();
+    let mut entries = Vec::new();
+    for (i, input) in sources.iter().enumerate() {
+        eprintln!("\n[{}/{}] {}", i + 1, sources.len(), input);
+        let result = render_page(input, &mut used_names);
+        if let Err(e) = &result {
+            eprintln!("✗ {}: {:#}", input, e);
+        }
+        entries.push(BatchEntry {
+            source: input.clone(),
+            result: result.map_err(|e| format!("{:#}", e)),
+        });
+    }
+
+    let index_path = out_dir.join("index.html");
+    std::fs::write(&index_path, html_builder::build_batch_index(&entries))
+        .context("Failed to write index.html")?;
+
+    let failures: Vec<_> = entries.iter().filter(|e| e.result.is_err()).collect();
+    eprintln!(
+        "\n✓ Rendered {} of {} sources into {} (index: {})",
+        entries.len() - failures.len(),
+        entries.len(),
+        out_dir.display(),
+        index_path.display()
+    );
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    eprintln!("✗ {} failed:", failures.len());
+    for entry in &failures {
+        if let Err(e) = &entry.result {
+            eprintln!("  {}: {}", entry.source, e);
+        }
+    }
+    anyhow::bail!("{} of {} sources failed", failures.len(), entries.len())
+}
+
+/// File name of the page for a repository called `name`, unique among
+/// `used_names` (which it is added to) and never `index.html`
+pub fn page_file_name(name: &str, used_names: &mut Vec<String>) -> String {
+    used_names.push(name.to_string());
+    utils::dedupe_names(used_names);
+    format!("{}.html", used_names[used_names.len() - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_source_list() {
+        let text = "# team repos\nowner/api\n\n  ./web  \n# ./old\ngitlab:group/shared\n";
+        assert_eq!(parse_source_list(text), vec!["owner/api", "./web", "gitlab:group/shared"]);
+    }
+
+    #[test]
+    fn test_failures_do_not_stop_the_batch() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let out_dir = temp_dir.path().join("out");
+        let sources: Vec<String> = ["./index", "broken", "./api"].iter().map(|s| s.to_string()).collect();
+
+        let mut rendered = Vec::new();
+        let result = render_all(&sources, &out_dir, |input, used_names| {
+            if input == "broken" {
+                anyhow::bail!("clone failed");
+            }
+            let name = input.trim_start_matches("./");
+            let file_name = page_file_name(name, used_names);
+            std::fs::write(out_dir.join(&file_name), "<html>").unwrap();
+            rendered.push(file_name.clone());
+            Ok(BatchPage {
+                name: name.to_string(),
+                file_name,
+                head_commit: "0123456789abcdef".to_string(),
+                rendered_files: 2,
+                total_files: 3,
+                html_size: 6,
+            })
+        });
+
+        assert_eq!(result.unwrap_err().to_string(), "1 of 3 sources failed");
+        // A source called `index` does not take the index page's name
+        assert_eq!(rendered, vec!["index-2.html", "api.html"]);
+        let index = std::fs::read_to_string(out_dir.join("index.html")).unwrap();
+        assert!(index.contains("<a href=\"index-2.html\">index</a>"));
+        assert!(index.contains("<a href=\"api.html\">api</a>"));
+        assert!(index.contains("<td><code>01234567</code></td>"));
+        assert!(index.contains("<h2>Failed (1)</h2>"));
+        assert!(index.contains("<code>broken</code> <span class='muted'>clone failed</span>"));
+    }
+}
@@ -0,0 +1,291 @@
+use crate::git_ops::{self, GitRef};
+use crate::utils::{bytes_human, slugify};
+use anyhow::{Context, Result};
+use git2::Repository;
+use std::fs::{self, File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Marker file touched every time a cache entry is used
+const LAST_USED_FILE: &str = "easycopy-last-used";
+
+/// A cached clone on disk
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub path: PathBuf,
+    pub url: String,
+    pub size: u64,
+    pub last_used: Option<SystemTime>,
+}
+
+/// Root of the clone cache: `$EASYCOPY_CACHE_DIR`, or `easycopy` under the
+/// platform cache directory (`$XDG_CACHE_HOME` or `~/.cache` on Linux)
+pub fn default_root() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("EASYCOPY_CACHE_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    dirs::cache_dir()
+        .map(|dir| dir.join("easycopy"))
+        .context("Could not determine a cache directory; set EASYCOPY_CACHE_DIR")
+}
+
+/// Normalize a remote URL so that equivalent spellings share a cache entry:
+/// scheme, credentials, a trailing `.git` and case in the host are dropped,
+/// and scp-style `git@host:owner/repo` becomes `host/owner/repo`.
+pub fn normalize_url(url: &str) -> String {
+    let mut rest = url.trim().trim_end_matches('/');
+    if let Some((_, after)) = rest.split_once("://") {
+        rest = after;
+    }
+    // Credentials only ever sit in the authority, before the first `/`
+    let authority_end = rest.find('/').unwrap_or(rest.len());
+    if let Some(at) = rest[..authority_end].rfind('@') {
+        rest = &rest[at + 1..];
+    }
+
+    let rest = rest.trim_end_matches(".git");
+    let (host, path) = match rest.split_once(['/', ':']) {
+        Some((host, path)) => (host, path.trim_start_matches('/')),
+        None => (rest, ""),
+    };
+    let host = host.split(':').next().unwrap_or(host).to_lowercase();
+
+    if path.is_empty() {
+        host
+    } else {
+        format!("{}/{}", host, path)
+    }
+}
+
+/// Directory name for a URL: a readable slug plus a hash of the normalized
+/// URL, so that e.g. `a/b-c` and `a-b/c` do not collide
+pub fn entry_name(url: &str) -> String {
+    let normalized = normalize_url(url);
+
+    // FNV-1a, stable across Rust releases unlike DefaultHasher
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in normalized.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    format!("{}-{:08x}", slugify(&normalized), hash as u32)
+}
+
+/// Lock on a cache entry; the entry is neither fetched into nor removed by
+/// another run until this is dropped
+pub struct EntryLock {
+    _file: File,
+}
+
+/// Get the cached clone for `url`, cloning it on first use and fetching the
+/// requested ref on later runs. Returns the path of the bare repository and
+/// the entry's lock.
+///
+/// Concurrent runs on the same URL wait for each other instead of writing
+/// into the same repository; keep the lock until rendering has finished so
+/// the repository doesn't change underneath it.
+pub fn fetch_cached(root: &Path, url: &str, git_ref: &GitRef, shallow: bool) -> Result<(PathBuf, EntryLock)> {
+    let path = root.join(entry_name(url));
+    fs::create_dir_all(root)
+        .with_context(|| format!("Failed to create cache directory {}", root.display()))?;
+
+    let lock = open_lock(&path)?;
+    match lock.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            eprintln!("⏳ Waiting for another easycopy run using {}", path.display());
+            lock.lock().context("Failed to lock cache entry")?;
+        }
+        Err(TryLockError::Error(e)) => return Err(e).context("Failed to lock cache entry"),
+    }
+
+    git_ops::fetch_into_cache(url, &path, git_ref, shallow)?;
+
+    fs::write(path.join(LAST_USED_FILE), url)
+        .context("Failed to update cache entry timestamp")?;
+    Ok((path, EntryLock { _file: lock }))
+}
+
+/// Lock file next to a cache entry; it stays behind so that every run locks
+/// the same file
+fn open_lock(entry: &Path) -> Result<File> {
+    let path = entry.with_extension("lock");
+    File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open lock file {}", path.display()))
+}
+
+/// List all cache entries, most recently used first
+pub fn list(root: &Path) -> Result<Vec<CacheEntry>> {
+    let mut entries = Vec::new();
+    if !root.is_dir() {
+        return Ok(entries);
+    }
+
+    for entry in fs::read_dir(root)? {
+        let path = entry?.path();
+        let Ok(repo) = Repository::open_bare(&path) else {
+            continue;
+        };
+
+        let url = repo
+            .find_remote("origin")
+            .ok()
+            .and_then(|r| r.url().map(str::to_string))
+            .unwrap_or_else(|| "(unknown)".to_string());
+        let last_used = fs::metadata(path.join(LAST_USED_FILE))
+            .and_then(|m| m.modified())
+            .ok();
+
+        entries.push(CacheEntry {
+            size: dir_size(&path),
+            path,
+            url,
+            last_used,
+        });
+    }
+
+    entries.sort_by_key(|e| std::cmp::Reverse(e.last_used));
+    Ok(entries)
+}
+
+/// Remove entries not used within `max_age`. Returns the removed entries;
+/// entries locked by a running fetch are left alone.
+pub fn prune(root: &Path, max_age: Duration) -> Result<Vec<CacheEntry>> {
+    let now = SystemTime::now();
+    let mut removed = Vec::new();
+
+    for entry in list(root)? {
+        let age = entry
+            .last_used
+            .and_then(|t| now.duration_since(t).ok())
+            .unwrap_or(Duration::MAX);
+        if age > max_age {
+            let lock = open_lock(&entry.path)?;
+            if lock.try_lock().is_err() {
+                continue;
+            }
+            fs::remove_dir_all(&entry.path)
+                .with_context(|| format!("Failed to remove {}", entry.path.display()))?;
+            removed.push(entry);
+        }
+    }
+    Ok(removed)
+}
+
+/// Remove every cache entry. Returns the removed entries.
+pub fn clear(root: &Path) -> Result<Vec<CacheEntry>> {
+    prune(root, Duration::ZERO)
+}
+
+/// Format an entry for `easycopy cache list`
+pub fn describe(entry: &CacheEntry) -> String {
+    let age = entry
+        .last_used
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .map(|d| format!("{}d ago", d.as_secs() / 86400))
+        .unwrap_or_else(|| "never".to_string());
+
+    format!(
+        "{}  {}  (last used {})\n    {}",
+        entry.url,
+        bytes_human(entry.size),
+        age,
+        entry.path.display()
+    )
+}
+
+/// Total size of all files under a directory
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| match e.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&e.path()),
+            Ok(t) if t.is_file() => e.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_normalize_url() {
+        let expected = "github.com/owner/repo";
+        assert_eq!(normalize_url("https://github.com/owner/repo"), expected);
+        assert_eq!(normalize_url("https://GitHub.com/owner/repo.git/"), expected);
+        assert_eq!(normalize_url("git@github.com:owner/repo.git"), expected);
+        assert_eq!(normalize_url("ssh://git@github.com/owner/repo"), expected);
+        assert_ne!(entry_name("https://h/a/b-c"), entry_name("https://h/a-b/c"));
+        assert_eq!(normalize_url("https://github.com/owner/repo@v1"), "github.com/owner/repo@v1");
+        assert_eq!(normalize_url("https://user:pw@h/a@b"), "h/a@b");
+    }
+
+    #[test]
+    fn test_fetch_cached_resolves_abbreviated_ids_and_revspecs() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        let repo = git_ops::tests::init_repo_with_commits(
+            &source,
+            &[("a.txt", "one"), ("a.txt", "two"), ("a.txt", "three")],
+        );
+        let head = repo.head().unwrap();
+        let branch = head.shorthand().unwrap().to_string();
+        let first = head.peel_to_commit().unwrap().parent(0).unwrap().parent_id(0).unwrap();
+        let url = format!("file://{}", source.display());
+        let cache_root = temp_dir.path().join("cache");
+
+        let short = GitRef::Commit(first.to_string()[..7].to_string());
+        let (path, _) = fetch_cached(&cache_root, &url, &short, true).unwrap();
+        let cached = Repository::open_bare(&path).unwrap();
+        assert_eq!(git_ops::resolve_commit(&cached, &short).unwrap().id(), first);
+
+        let revspec = GitRef::Commit(format!("{}~2", branch));
+        fetch_cached(&cache_root, &url, &revspec, true).unwrap();
+        assert_eq!(git_ops::resolve_commit(&cached, &revspec).unwrap().id(), first);
+    }
+
+    #[test]
+    fn test_fetch_cached_updates_incrementally() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        let cache_root = temp_dir.path().join("cache");
+        let repo = git_ops::tests::init_repo_with_commits(&source, &[("a.txt", "one")]);
+        let url = format!("file://{}", source.display());
+
+        let (path, _) = fetch_cached(&cache_root, &url, &GitRef::Default, true).unwrap();
+        let cached = Repository::open_bare(&path).unwrap();
+        let first = git_ops::resolve_commit(&cached, &GitRef::Default).unwrap().id();
+        assert_eq!(first, repo.head().unwrap().peel_to_commit().unwrap().id());
+
+        git_ops::tests::init_repo_with_commits(&source, &[("a.txt", "two")]);
+        let (again, _) = fetch_cached(&cache_root, &url, &GitRef::Default, true).unwrap();
+        assert_eq!(again, path);
+        let cached = Repository::open_bare(&path).unwrap();
+        let second = git_ops::resolve_commit(&cached, &GitRef::Default).unwrap();
+        assert_eq!(second.parent_id(0).unwrap(), first);
+
+        let entries = list(&cache_root).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].url, url);
+        assert!(prune(&cache_root, Duration::from_secs(3600)).unwrap().is_empty());
+
+        // A run holds the lock until it has rendered, so clearing skips the entry
+        let (_, held) = fetch_cached(&cache_root, &url, &GitRef::Default, true).unwrap();
+        assert!(clear(&cache_root).unwrap().is_empty());
+        drop(held);
+        assert_eq!(clear(&cache_root).unwrap().len(), 1);
+        assert!(list(&cache_root).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use crate::file_analyzer::{self, FileInfo, RenderDecision, ScanOptions, SymlinkPolicy};
+use crate::git_ops::{self, GitRef};
+use anyhow::{Context, Result};
+use git2::{Delta, DiffFindOptions, DiffOptions, FileMode, Repository, Tree};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Which changes of a working copy to render (`--changes`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeSet {
+    /// Everything not yet committed, staged or not, including untracked files
+    Working,
+    /// Only what is staged in the index
+    Staged,
+    /// The working tree compared to a base revision
+    Since(String),
+}
+
+impl FromStr for ChangeSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "working" => Ok(Self::Working),
+            "staged" => Ok(Self::Staged),
+            _ => match s.strip_prefix("since:") {
+                Some(rev) if !rev.is_empty() => Ok(Self::Since(rev.to_string())),
+                _ => Err("expected working, staged or since:<rev>".to_string()),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for ChangeSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Working => write!(f, "working"),
+            Self::Staged => write!(f, "staged"),
+            Self::Since(rev) => write!(f, "since {}", rev),
+        }
+    }
+}
+
+/// Collect the files of a working copy that were added, modified or renamed,
+/// plus deleted files (reason "deleted"). Staged changes are read from the
+/// index, everything else from disk.
+pub fn collect_changed_files(
+    repo_dir: &Path,
+    changes: &ChangeSet,
+    options: &ScanOptions,
+) -> Result<Vec<FileInfo>> {
+    let repo = git_ops::open_repo(repo_dir)?;
+    if repo.is_bare() {
+        anyhow::bail!("--changes needs a working tree, but {} is a bare repository", repo_dir.display());
+    }
+
+    let mut diff_options = DiffOptions::new();
+    diff_options
+        .include_untracked(*changes != ChangeSet::Staged)
+        .recurse_untracked_dirs(true);
+    if let Some(subdir) = &options.subdir {
+        diff_options.pathspec(subdir);
+    }
+
+    // An unborn HEAD compares against the empty tree
+    let head_tree: Option<Tree> = repo.head().ok().and_then(|h| h.peel_to_tree().ok());
+    let mut diff = match changes {
+        ChangeSet::Working => repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut diff_options)),
+        ChangeSet::Staged => repo.diff_tree_to_index(head_tree.as_ref(), None, Some(&mut diff_options)),
+        ChangeSet::Since(rev) => {
+            let base = git_ops::resolve_commit(&repo, &GitRef::Commit(rev.clone()))?
+                .tree()
+                .context("Failed to read base tree")?;
+            repo.diff_tree_to_workdir_with_index(Some(&base), Some(&mut diff_options))
+        }
+    }
+    .context("Failed to diff working copy")?;
+
+    let mut find_options = DiffFindOptions::new();
+    find_options.renames(true).for_untracked(true);
+    diff.find_similar(Some(&mut find_options))
+        .context("Failed to detect renames")?;
+
+    let odb = repo.odb().context("Failed to open object database")?;
+    let mut infos = Vec::new();
+    for delta in diff.deltas() {
+        let deleted = delta.status() == Delta::Deleted;
+        let file = if deleted { delta.old_file() } else { delta.new_file() };
+        let Some(rel) = file.path().map(|p| p.to_string_lossy().replace('\\', "/")) else {
+            continue;
+        };
+        if !options.filter.allows(&rel) {
+            continue;
+        }
+
+        match delta.status() {
+            Delta::Deleted => infos.push(deleted_entry(&repo, rel, file.id())),
+            Delta::Added | Delta::Modified | Delta::Renamed | Delta::Copied | Delta::Untracked
+            | Delta::Typechange | Delta::Conflicted => {
+                if file.mode() == FileMode::Link {
+                    if options.symlinks != SymlinkPolicy::Skip {
+                        let target = link_target(&repo, repo_dir, changes, &rel, file.id());
+                        infos.push(file_analyzer::symlink_entry(PathBuf::from(&rel), rel, target));
+                    }
+                    continue;
+                }
+                let info = match changes {
+                    ChangeSet::Staged => file_analyzer::blob_entry(&repo, &odb, rel, file.id(), options)?,
+                    _ => file_analyzer::decide_file(&repo_dir.join(&rel), repo_dir, options)?,
+                };
+                infos.push(info);
+            }
+            _ => {}
+        }
+    }
+
+    infos.sort_by(|a, b| a.rel.cmp(&b.rel));
+    Ok(infos)
+}
+
+/// A deleted file, listed with its size before deletion
+fn deleted_entry(repo: &Repository, rel: String, oid: git2::Oid) -> FileInfo {
+    let size = repo.find_blob(oid).map(|b| b.size() as u64).unwrap_or(0);
+    FileInfo {
+        path: PathBuf::from(&rel),
+        rel,
+        size,
+        contents: None,
+        symlink_target: None,
+        decision: RenderDecision {
+            include: false,
+            reason: "deleted".to_string(),
+        },
+    }
+}
+
+/// Where a changed symlink points, from the index or the working tree
+fn link_target(repo: &Repository, repo_dir: &Path, changes: &ChangeSet, rel: &str, oid: git2::Oid) -> String {
+    match changes {
+        ChangeSet::Staged => repo
+            .find_blob(oid)
+            .map(|b| String::from_utf8_lossy(b.content()).into_owned())
+            .unwrap_or_default(),
+        _ => std::fs::read_link(repo_dir.join(rel))
+            .map(|t| t.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn summary(infos: &[FileInfo]) -> Vec<(String, String)> {
+        infos.iter().map(|f| (f.rel.clone(), f.decision.reason.clone())).collect()
+    }
+
+    fn pairs(expected: &[(&str, &str)]) -> Vec<(String, String)> {
+        expected.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect()
+    }
+
+    #[test]
+    fn test_collect_changed_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let repo = git_ops::tests::init_repo_with_commits(
+            root,
+            &[("a.txt", "a"), ("b.txt", "b"), ("c.txt", "the same content, renamed")],
+        );
+
+        // Unstaged edit, staged new file edited again on disk, deletion, rename
+        fs::write(root.join("a.txt"), "a changed").unwrap();
+        fs::write(root.join("d.txt"), "staged").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("d.txt")).unwrap();
+        fs::write(root.join("d.txt"), "staged, then edited").unwrap();
+        fs::remove_file(root.join("b.txt")).unwrap();
+        index.remove_path(Path::new("b.txt")).unwrap();
+        fs::rename(root.join("c.txt"), root.join("e.txt")).unwrap();
+        index.remove_path(Path::new("c.txt")).unwrap();
+        index.add_path(Path::new("e.txt")).unwrap();
+        index.write().unwrap();
+
+        let options = ScanOptions::for_tests();
+
+        let working = collect_changed_files(root, &ChangeSet::Working, &options).unwrap();
+        assert_eq!(
+            summary(&working),
+            pairs(&[("a.txt", "ok"), ("b.txt", "deleted"), ("d.txt", "ok"), ("e.txt", "ok")])
+        );
+        assert_eq!(working[2].read_to_string().unwrap(), "staged, then edited");
+
+        let staged = collect_changed_files(root, &ChangeSet::Staged, &options).unwrap();
+        assert_eq!(summary(&staged), pairs(&[("b.txt", "deleted"), ("d.txt", "ok"), ("e.txt", "ok")]));
+        assert_eq!(staged[1].read_to_string().unwrap(), "staged");
+
+        // Since the first commit, b.txt and c.txt were added and then removed
+        let since = ChangeSet::from_str("since:HEAD~2").unwrap();
+        let infos = collect_changed_files(root, &since, &options).unwrap();
+        assert_eq!(summary(&infos), pairs(&[("a.txt", "ok"), ("d.txt", "ok"), ("e.txt", "ok")]));
+
+        assert!(ChangeSet::from_str("since:").is_err());
+        assert!(ChangeSet::from_str("everything").is_err());
+    }
+}
@@ -0,0 +1,409 @@
+use git2::{Config, Cred, CredentialType, ErrorClass, ErrorCode, FetchOptions, RemoteCallbacks};
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::process::Command;
+use std::rc::Rc;
+
+/// SSH keys tried after the agent, in the order ssh itself prefers them
+const DEFAULT_SSH_KEYS: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// Where credentials for a remote may come from
+#[derive(Debug, Clone, Default)]
+pub struct CredentialSources {
+    /// Access token sent as the HTTPS password
+    pub token: Option<String>,
+    /// Ask the git credential helpers configured in git config
+    pub credential_helper: bool,
+    /// Program prompted for a username and password, as git does
+    pub askpass: Option<PathBuf>,
+    /// Ask a running ssh-agent for keys
+    pub ssh_agent: bool,
+    /// Private key files to try, in order
+    pub ssh_keys: Vec<PathBuf>,
+    pub ssh_passphrase: Option<String>,
+}
+
+impl CredentialSources {
+    /// Sources for `url` from the environment: the token comes from
+    /// `EASYCOPY_TOKEN`, or `GITHUB_TOKEN`/`GH_TOKEN` and `GITLAB_TOKEN` for
+    /// exactly those hosts; the key from `EASYCOPY_SSH_KEY` or `~/.ssh`.
+    ///
+    /// `parent_url` is the repository that named `url` (for submodules):
+    /// `EASYCOPY_TOKEN` is then only sent if both are on the same host.
+    /// Plain `http://` remotes never get a token, helper or askpass password.
+    pub fn from_env(url: &str, parent_url: Option<&str>) -> Self {
+        Self::from_vars(url, parent_url, &|name| std::env::var(name).ok())
+    }
+
+    fn from_vars(url: &str, parent_url: Option<&str>, var: &dyn Fn(&str) -> Option<String>) -> Self {
+        let env = |name: &str| var(name).filter(|v| !v.is_empty());
+
+        let remote = Remote::parse(url);
+        let host = remote.as_ref().map(|r| r.host.as_str());
+        let plain_http = remote.as_ref().is_some_and(|r| r.scheme == "http");
+        let host_tokens: &[&str] = match host {
+            Some("github.com") => &["GITHUB_TOKEN", "GH_TOKEN"],
+            Some("gitlab.com") => &["GITLAB_TOKEN"],
+            _ => &[],
+        };
+        let same_host = match parent_url {
+            Some(parent) => host.is_some() && Remote::parse(parent).map(|r| r.host).as_deref() == host,
+            None => true,
+        };
+        let token = same_host
+            .then(|| env("EASYCOPY_TOKEN"))
+            .flatten()
+            .or_else(|| host_tokens.iter().find_map(|name| env(name)))
+            .filter(|_| !plain_http);
+
+        let mut ssh_keys: Vec<PathBuf> = env("EASYCOPY_SSH_KEY").map(PathBuf::from).into_iter().collect();
+        if let Some(ssh_dir) = dirs::home_dir().map(|home| home.join(".ssh")) {
+            ssh_keys.extend(
+                DEFAULT_SSH_KEYS
+                    .iter()
+                    .map(|name| ssh_dir.join(name))
+                    .filter(|path| path.is_file()),
+            );
+        }
+
+        Self {
+            token,
+            credential_helper: !plain_http,
+            askpass: env("GIT_ASKPASS").map(PathBuf::from).filter(|_| !plain_http),
+            ssh_agent: true,
+            ssh_keys,
+            ssh_passphrase: env("EASYCOPY_SSH_PASSPHRASE"),
+        }
+    }
+}
+
+/// Supplies credentials to libgit2, trying each source once, and turns
+/// authentication failures into errors that say what was tried
+#[derive(Clone)]
+pub struct Authenticator {
+    sources: CredentialSources,
+    tried: Rc<RefCell<Vec<String>>>,
+}
+
+impl Authenticator {
+    pub fn new(sources: CredentialSources) -> Self {
+        Self {
+            sources,
+            tried: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Authenticator using the credentials available from the environment
+    /// (see `CredentialSources::from_env`)
+    pub fn from_env(url: &str, parent_url: Option<&str>) -> Self {
+        Self::new(CredentialSources::from_env(url, parent_url))
+    }
+
+    /// Fetch options whose callbacks answer credential requests
+    pub fn fetch_options(&self) -> FetchOptions<'static> {
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(self.callbacks());
+        fetch_options
+    }
+
+    /// Remote callbacks that answer credential requests. libgit2 calls back
+    /// until it gets in or we give up, so each source is offered only once.
+    pub fn callbacks(&self) -> RemoteCallbacks<'static> {
+        let sources = self.sources.clone();
+        let tried = Rc::clone(&self.tried);
+        let mut ssh_keys = sources.ssh_keys.clone().into_iter();
+        let mut token = sources.token.clone();
+        let mut askpass = sources.askpass.clone();
+        let mut ssh_agent = sources.ssh_agent;
+        let mut credential_helper = sources.credential_helper;
+        let mut default = true;
+
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(move |url, username_from_url, allowed| {
+            let user = username_from_url.unwrap_or("git");
+            let record = |what: &str| tried.borrow_mut().push(what.to_string());
+
+            if allowed.contains(CredentialType::USERNAME) {
+                return Cred::username(user);
+            }
+
+            if allowed.contains(CredentialType::SSH_KEY) {
+                if std::mem::take(&mut ssh_agent) {
+                    record("ssh-agent");
+                    return Cred::ssh_key_from_agent(user);
+                }
+                for key in ssh_keys.by_ref() {
+                    record(&key.display().to_string());
+                    if let Ok(cred) = Cred::ssh_key(user, None, &key, sources.ssh_passphrase.as_deref()) {
+                        return Ok(cred);
+                    }
+                }
+            }
+
+            if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+                if let Some(token) = token.take() {
+                    record("access token");
+                    let user = username_from_url.unwrap_or_else(|| token_username(url));
+                    return Cred::userpass_plaintext(user, &token);
+                }
+                if std::mem::take(&mut credential_helper) {
+                    let cred = Config::open_default()
+                        .and_then(|config| Cred::credential_helper(&config, url, username_from_url));
+                    if let Ok(cred) = cred {
+                        record("git credential helper");
+                        return Ok(cred);
+                    }
+                }
+                if let Some(program) = askpass.take() {
+                    record("GIT_ASKPASS");
+                    return askpass_cred(&program, url, username_from_url);
+                }
+            }
+
+            if allowed.contains(CredentialType::DEFAULT) && std::mem::take(&mut default) {
+                return Cred::default();
+            }
+
+            Err(git2::Error::new(ErrorCode::Auth, ErrorClass::Callback, "no more credentials to try"))
+        });
+        callbacks
+    }
+
+    /// Convert a libgit2 error from talking to `url`, explaining
+    /// authentication failures
+    pub fn check<T>(&self, url: &str, result: Result<T, git2::Error>) -> anyhow::Result<T> {
+        result.map_err(|e| {
+            if e.code() != ErrorCode::Auth {
+                return e.into();
+            }
+
+            let tried = self.tried.borrow();
+            let attempted = if tried.is_empty() {
+                "no credentials were available".to_string()
+            } else {
+                format!("tried {}", tried.join(", "))
+            };
+            anyhow::anyhow!(
+                "Authentication failed for {} ({}). Set EASYCOPY_TOKEN, configure a git credential \
+                 helper or GIT_ASKPASS, or add your SSH key to ssh-agent. ({})",
+                url,
+                attempted,
+                e.message()
+            )
+        })
+    }
+}
+
+/// Username paired with a token: GitLab wants `oauth2`, GitHub and most
+/// others accept anything non-empty
+fn token_username(url: &str) -> &'static str {
+    match Remote::parse(url) {
+        Some(remote) if remote.host.starts_with("gitlab.") => "oauth2",
+        _ => "x-access-token",
+    }
+}
+
+/// Scheme and host of a remote URL
+#[derive(Debug, PartialEq)]
+struct Remote {
+    /// `https`, `http`, `ssh`, `git`, ...; scp-style `host:path` is `ssh`
+    scheme: String,
+    /// Lowercase host name, without user info or port
+    host: String,
+}
+
+impl Remote {
+    /// Parse `scheme://[user@]host[:port]/path` or scp-style
+    /// `[user@]host:path`. Local paths and `file://` URLs have no host.
+    fn parse(url: &str) -> Option<Self> {
+        let (scheme, authority) = match url.split_once("://") {
+            Some((scheme, rest)) => (scheme.to_lowercase(), rest.split('/').next().unwrap_or(rest)),
+            None => {
+                let (authority, _) = url.split_once(':')?;
+                // `C:\path` or `./a:b` are paths, not remotes
+                if authority.contains('/') || authority.contains('\\') || authority.len() < 2 {
+                    return None;
+                }
+                ("ssh".to_string(), authority)
+            }
+        };
+        let host_port = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+        let host = match host_port.strip_prefix('[') {
+            Some(ipv6) => ipv6.split(']').next().unwrap_or(ipv6),
+            None => host_port.split(':').next().unwrap_or(host_port),
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some(Self { scheme, host: host.to_lowercase() })
+    }
+}
+
+/// Ask a `GIT_ASKPASS` program for a username (unless the URL has one) and
+/// password, using the same prompts as git
+fn askpass_cred(program: &PathBuf, url: &str, username: Option<&str>) -> Result<Cred, git2::Error> {
+    let ask = |prompt: String| -> Result<String, git2::Error> {
+        let output = Command::new(program)
+            .arg(prompt)
+            .output()
+            .map_err(|e| git2::Error::from_str(&format!("Failed to run GIT_ASKPASS: {}", e)))?;
+        if !output.status.success() {
+            return Err(git2::Error::from_str("GIT_ASKPASS exited with an error"));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim_end_matches(['\r', '\n']).to_string())
+    };
+
+    let username = match username {
+        Some(username) => username.to_string(),
+        None => ask(format!("Username for '{}': ", url))?,
+    };
+    let password = ask(format!("Password for '{}@{}': ", username, url))?;
+    Cred::userpass_plaintext(&username, &password)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::build::RepoBuilder;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use tempfile::TempDir;
+
+    /// An HTTP remote that rejects every request with 401 and reports the
+    /// Basic credentials it was sent
+    fn rejecting_remote() -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/private.git", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { break };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).map(|n| n > 2).unwrap_or(false) {
+                    if let Some(value) = line.trim().strip_prefix("Authorization: Basic ") {
+                        let _ = tx.send(value.to_string());
+                    }
+                    line.clear();
+                }
+                let _ = stream.write_all(
+                    b"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"git\"\r\n\
+                      Content-Length: 0\r\nConnection: close\r\n\r\n",
+                );
+            }
+        });
+        (url, rx)
+    }
+
+    fn clone_with(sources: CredentialSources, url: &str, dest: &std::path::Path) -> anyhow::Result<()> {
+        let auth = Authenticator::new(sources);
+        let mut builder = RepoBuilder::new();
+        builder.bare(true).fetch_options(auth.fetch_options());
+        auth.check(url, builder.clone(url, dest)).map(|_| ())
+    }
+
+    #[test]
+    fn test_token_is_sent_once_and_failure_is_explained() {
+        let temp_dir = TempDir::new().unwrap();
+        let (url, sent) = rejecting_remote();
+
+        let sources = CredentialSources {
+            token: Some("s3cret".to_string()),
+            ..CredentialSources::default()
+        };
+        let err = clone_with(sources, &url, &temp_dir.path().join("a")).unwrap_err();
+
+        // base64("x-access-token:s3cret")
+        assert_eq!(sent.try_iter().collect::<Vec<_>>(), vec!["eC1hY2Nlc3MtdG9rZW46czNjcmV0"]);
+        let message = err.to_string();
+        assert!(message.contains("Authentication failed"), "{}", message);
+        assert!(message.contains("tried access token"), "{}", message);
+    }
+
+    #[test]
+    fn test_tokens_only_go_to_their_own_host() {
+        let vars = |name: &str| match name {
+            "EASYCOPY_TOKEN" => Some("generic".to_string()),
+            "GITHUB_TOKEN" => Some("gh".to_string()),
+            _ => None,
+        };
+        let token = |url: &str, parent: Option<&str>| CredentialSources::from_vars(url, parent, &vars).token;
+
+        assert_eq!(token("https://example.com/org/repo.git", None).as_deref(), Some("generic"));
+        assert_eq!(token("http://example.com/org/repo.git", None), None);
+        assert!(!CredentialSources::from_vars("http://example.com/r.git", None, &vars).credential_helper);
+
+        // Submodules on another host only get that host's own token
+        let parent = Some("https://example.com/org/app.git");
+        assert_eq!(token("https://example.com/org/lib.git", parent).as_deref(), Some("generic"));
+        assert_eq!(token("https://evil.example.net/lib.git", parent), None);
+        assert_eq!(token("https://github.com.evil.net/lib.git", parent), None);
+        assert_eq!(token("git@github.com:org/lib.git", parent).as_deref(), Some("gh"));
+
+        assert_eq!(
+            Remote::parse("ssh://git@Git.Example.com:2222/team/repo.git"),
+            Some(Remote { scheme: "ssh".to_string(), host: "git.example.com".to_string() })
+        );
+        assert_eq!(Remote::parse("https://a@b@example.com/x").unwrap().host, "example.com");
+        assert_eq!(Remote::parse("host:path/repo.git").unwrap().scheme, "ssh");
+        assert_eq!(Remote::parse("/srv/git/repo.git"), None);
+        assert_eq!(Remote::parse("file:///srv/git/repo.git"), None);
+    }
+
+    /// Clone over `git://` from a `git daemon` stand-in: the protocol has no
+    /// authentication, so no credential may be offered or reported
+    #[test]
+    fn test_git_daemon_clone_offers_no_credentials() {
+        let temp_dir = TempDir::new().unwrap();
+        let base = temp_dir.path().join("served");
+        let source = git2::Repository::init_bare(base.join("repo.git")).unwrap();
+        let tree = source.treebuilder(None).unwrap().write().unwrap();
+        let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+        let tree = source.find_tree(tree).unwrap();
+        source.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[]).unwrap();
+
+        let Some((daemon, port)) = crate::git_ops::tests::git_daemon(&base) else {
+            eprintln!("skipping: git daemon is not available");
+            return;
+        };
+
+        let url = format!("git://127.0.0.1:{}/repo.git", port);
+        let auth = Authenticator::new(CredentialSources {
+            token: Some("s3cret".to_string()),
+            ssh_agent: true,
+            ..CredentialSources::default()
+        });
+        let mut builder = RepoBuilder::new();
+        builder.bare(true).fetch_options(auth.fetch_options());
+        let result = auth.check(&url, builder.clone(&url, &temp_dir.path().join("clone")));
+        drop(daemon);
+
+        result.unwrap();
+        assert!(auth.tried.borrow().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_askpass_prompts_for_username_and_password() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let script = temp_dir.path().join("askpass.sh");
+        std::fs::write(&script, "#!/bin/sh\ncase \"$1\" in Username*) echo alice ;; *) echo hunter2 ;; esac\n")
+            .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let (url, sent) = rejecting_remote();
+
+        let sources = CredentialSources {
+            askpass: Some(script),
+            ..CredentialSources::default()
+        };
+        let err = clone_with(sources, &url, &temp_dir.path().join("b")).unwrap_err();
+
+        // base64("alice:hunter2")
+        assert_eq!(sent.try_iter().collect::<Vec<_>>(), vec!["YWxpY2U6aHVudGVyMg=="]);
+        assert!(err.to_string().contains("tried GIT_ASKPASS"));
+    }
+}
@@ -1,60 +1,292 @@
+use crate::diff_view::FileDiff;
 use crate::file_analyzer::FileInfo;
+use crate::tokens::{self, TokenOptions};
 use anyhow::Result;
-use std::fs;
+
+/// A rendered file, not yet numbered
+struct Document {
+    source: String,
+    rel: String,
+    content: String,
+}
+
+impl Document {
+    fn to_cxml(&self, index: usize) -> String {
+        format!(
+            "<document index=\"{}\">\n<source>{}</source>\n<document_content>\n{}\n</document_content>\n</document>",
+            index, self.source, self.content
+        )
+    }
+}
 
 /// Generate CXML format text for LLM consumption
-pub fn generate_cxml(file_infos: &[FileInfo]) -> Result<String> {
-    let mut lines = Vec::new();
-    lines.push("<documents>".to_string());
+pub fn generate_cxml(file_infos: &[FileInfo], tokens: &TokenOptions) -> Result<String> {
+    let mut docs = Vec::new();
+    collect_documents(&mut docs, "", file_infos);
+    Ok(assemble(&docs, tokens))
+}
 
-    let rendered: Vec<_> = file_infos
-        .iter()
-        .filter(|f| f.decision.include)
-        .collect();
+/// Generate one CXML document list for several repositories, numbered
+/// throughout, with each `<source>` prefixed by its repository name
+pub fn generate_combined_cxml(repos: &[(&str, &[FileInfo])], tokens: &TokenOptions) -> Result<String> {
+    let mut docs = Vec::new();
+    for (repo_name, file_infos) in repos {
+        collect_documents(&mut docs, &format!("{}/", repo_name), file_infos);
+    }
+    Ok(assemble(&docs, tokens))
+}
 
-    for (index, info) in rendered.iter().enumerate() {
-        lines.push(format!("<document index=\"{}\">", index + 1));
-        lines.push(format!("<source>{}</source>", info.rel));
-        lines.push("<document_content>".to_string());
+/// Read every rendered file into a `Document`
+fn collect_documents(docs: &mut Vec<Document>, source_prefix: &str, file_infos: &[FileInfo]) {
+    for info in file_infos.iter().filter(|f| f.decision.include) {
+        let content = match info.read_to_string() {
+            Ok(content) => content,
+            Err(e) => format!("Failed to read: {}", e),
+        };
+        docs.push(Document {
+            source: format!("{}{}", source_prefix, info.rel),
+            rel: info.rel.clone(),
+            content,
+        });
+    }
+}
 
-        match fs::read_to_string(&info.path) {
-            Ok(content) => lines.push(content),
-            Err(e) => lines.push(format!("Failed to read: {}", e)),
+/// Join the documents into `<documents>`, keeping to the token budget if
+/// there is one
+fn assemble(docs: &[Document], tokens: &TokenOptions) -> String {
+    match tokens.budget {
+        None => join(docs, &vec![true; docs.len()], &[]),
+        Some(budget) => fit_budget(docs, tokens, budget),
+    }
+}
+
+/// Join the kept documents, numbered in path order, followed by the lines
+/// of the `<omitted_documents>` list if there is one
+fn join(docs: &[Document], keep: &[bool], omitted: &[String]) -> String {
+    let mut parts = vec!["<documents>".to_string()];
+    let kept = docs.iter().zip(keep).filter(|(_, keep)| **keep);
+    for (index, (doc, _)) in kept.enumerate() {
+        parts.push(doc.to_cxml(index + 1));
+    }
+    parts.extend_from_slice(omitted);
+    parts.push("</documents>".to_string());
+    parts.join("\n")
+}
+
+/// Take documents in the chosen priority order while they fit in `budget`,
+/// leaving room for the list of left-out files. The list names as many of
+/// them as fit and sums up the rest in one line.
+///
+/// Each piece of the document is counted once, with its line break, and the
+/// pieces are added up; the finished text is counted only for the report.
+fn fit_budget(docs: &[Document], tokens: &TokenOptions, budget: usize) -> String {
+    let count = |line: &str| tokens.tokenizer.count(&format!("{}\n", line));
+    let costs: Vec<usize> = docs.iter().map(|doc| count(&doc.to_cxml(docs.len()))).collect();
+    let files: Vec<(&str, usize)> = docs.iter().zip(&costs).map(|(doc, cost)| (doc.rel.as_str(), *cost)).collect();
+    let order = tokens.order.priority(&files);
+
+    let frame = count("<documents>") + count("</documents>");
+    let everything = frame + costs.iter().sum::<usize>();
+    if everything <= budget {
+        return join(docs, &vec![true; docs.len()], &[]);
+    }
+
+    // Something is left out, so keep room for the list's opening and closing
+    // lines and for a summary of entries that do not fit
+    let open = format!("<omitted_documents token_budget=\"{}\">", budget);
+    let summary_cost = count(&more_omitted(docs.len(), everything));
+    let mut used = frame + count(&open) + count("</omitted_documents>") + summary_cost;
+
+    let mut keep = vec![false; docs.len()];
+    for &i in &order {
+        if used + costs[i] <= budget {
+            keep[i] = true;
+            used += costs[i];
+        }
+    }
+
+    let mut omitted = vec![open];
+    let (mut unlisted, mut unlisted_tokens) = (0, 0);
+    for i in (0..docs.len()).filter(|&i| !keep[i]) {
+        let line = format!("<source tokens=\"{}\">{}</source>", costs[i], docs[i].source);
+        let cost = count(&line);
+        if unlisted == 0 && used + cost <= budget {
+            omitted.push(line);
+            used += cost;
+        } else {
+            unlisted += 1;
+            unlisted_tokens += costs[i];
         }
+    }
+    if unlisted > 0 {
+        omitted.push(more_omitted(unlisted, unlisted_tokens));
+    }
+    omitted.push("</omitted_documents>".to_string());
 
-        lines.push("</document_content>".to_string());
-        lines.push("</document>".to_string());
+    let text = join(docs, &keep, &omitted);
+    let total = tokens.tokenizer.count(&text);
+    let left_out: Vec<usize> = (0..docs.len()).filter(|&i| !keep[i]).collect();
+    eprintln!(
+        "✂️  Token budget {}: kept {} of {} files ({} tokens, {}), left out:",
+        budget,
+        docs.len() - left_out.len(),
+        docs.len(),
+        total,
+        tokens.tokenizer.name()
+    );
+    for i in left_out {
+        eprintln!("   {} ({} tokens)", docs[i].source, tokens::tokens_human(costs[i]));
     }
+    if total > budget {
+        eprintln!(
+            "⚠️  Token budget {} is too small for the document frame; it takes {} tokens",
+            budget, total
+        );
+    }
+    text
+}
 
-    lines.push("</documents>".to_string());
-    Ok(lines.join("\n"))
+/// Summary line for left-out files that did not fit in the omitted list
+fn more_omitted(count: usize, tokens: usize) -> String {
+    format!("<more_omitted_documents count=\"{}\" tokens=\"{}\"/>", count, tokens)
+}
+
+/// Generate a structured diff for LLM consumption: one `<file>` per changed
+/// file with its status and line counts, and the unified hunks inside
+pub fn generate_diff_cxml(base: &str, head: &str, files: &[FileDiff]) -> String {
+    let mut lines = Vec::new();
+    lines.push(format!("<diff base=\"{}\" head=\"{}\">", base, head));
+
+    for (index, file) in files.iter().enumerate() {
+        let old_path = match (&file.old_path, &file.new_path) {
+            (Some(old), Some(new)) if old != new => format!(" old_path=\"{}\"", escape_attr(old)),
+            _ => String::new(),
+        };
+        lines.push(format!(
+            "<file index=\"{}\" path=\"{}\"{} status=\"{}\" additions=\"{}\" deletions=\"{}\">",
+            index + 1,
+            escape_attr(file.path()),
+            old_path,
+            file.status,
+            file.additions,
+            file.deletions
+        ));
+        if let Some(note) = &file.note {
+            lines.push(format!("<note>{}</note>", note));
+        }
+        for hunk in &file.hunks {
+            lines.push(format!("<hunk header=\"{}\">", escape_attr(&hunk.header)));
+            for line in &hunk.lines {
+                lines.push(format!("{}{}", line.origin, line.content.trim_end_matches('\n')));
+            }
+            lines.push("</hunk>".to_string());
+        }
+        lines.push("</file>".to_string());
+    }
+
+    lines.push("</diff>".to_string());
+    lines.join("\n")
+}
+
+/// Escape a value for a double-quoted attribute
+fn escape_attr(value: &str) -> String {
+    html_escape::encode_double_quoted_attribute(value).to_string()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::file_analyzer::RenderDecision;
-    use std::path::PathBuf;
+    use crate::file_analyzer::{file, text_file};
 
     #[test]
     fn test_generate_cxml() {
-        let files = vec![
-            FileInfo {
-                path: PathBuf::from("test.txt"),
-                rel: "test.txt".to_string(),
-                size: 100,
-                decision: RenderDecision {
-                    include: true,
-                    reason: "ok".to_string(),
-                },
-            },
-        ];
+        let files = vec![file("test.txt", 100, "ok")];
 
-        let cxml = generate_cxml(&files);
+        let cxml = generate_cxml(&files, &TokenOptions::default());
         assert!(cxml.is_ok());
         let content = cxml.unwrap();
         assert!(content.contains("<documents>"));
         assert!(content.contains("<source>test.txt</source>"));
         assert!(content.contains("</documents>"));
     }
+
+    #[test]
+    fn test_generate_combined_cxml() {
+        let api = vec![text_file("main.rs", "fn main() {}", "ok")];
+        let web = vec![text_file("main.rs", "console.log(1)", "ok"), text_file("index.html", "<p>", "ok")];
+
+        let cxml = generate_combined_cxml(&[("api", &api), ("web", &web)], &TokenOptions::default()).unwrap();
+        assert!(cxml.contains("<document index=\"1\">\n<source>api/main.rs</source>"));
+        assert!(cxml.contains("<document index=\"2\">\n<source>web/main.rs</source>"));
+        assert!(cxml.contains("<document index=\"3\">\n<source>web/index.html</source>"));
+        assert_eq!(cxml.matches("<documents>").count(), 1);
+    }
+
+    #[test]
+    fn test_token_budget() {
+        let files = vec![
+            text_file("a.rs", &"a".repeat(400), "ok"),
+            text_file("b.rs", &"b".repeat(40), "ok"),
+            text_file("c.rs", &"c".repeat(40), "ok"),
+        ];
+        let tokens = TokenOptions {
+            tokenizer: crate::tokens::Tokenizer::Estimate,
+            budget: Some(120),
+            order: crate::tokens::BudgetOrder::Path,
+        };
+
+        let cxml = generate_cxml(&files, &tokens).unwrap();
+        assert!(crate::tokens::estimate(&cxml) <= 120);
+        assert!(cxml.contains("<document index=\"1\">\n<source>b.rs</source>"));
+        assert!(cxml.contains("<document index=\"2\">\n<source>c.rs</source>"));
+        assert!(cxml.contains("<omitted_documents token_budget=\"120\">\n<source tokens=\"124\">a.rs</source>"));
+
+        // Too small for the list itself: the entries are summed up instead
+        let tokens = TokenOptions { budget: Some(30), ..tokens };
+        let cxml = generate_cxml(&files, &tokens).unwrap();
+        assert!(!cxml.contains("<document "));
+        assert!(cxml.contains("<more_omitted_documents count=\"3\" tokens=\"192\"/>"));
+    }
+
+    #[test]
+    fn test_generate_diff_cxml() {
+        use crate::diff_view::{DiffHunk, DiffLine};
+
+        let line = |origin, content: &str| DiffLine {
+            origin,
+            old_lineno: None,
+            new_lineno: None,
+            content: format!("{}\n", content),
+        };
+        let files = vec![FileDiff {
+            old_path: Some("old.rs".to_string()),
+            new_path: Some("new.rs".to_string()),
+            status: "renamed".to_string(),
+            additions: 1,
+            deletions: 1,
+            note: None,
+            hunks: vec![DiffHunk {
+                header: "@@ -1 +1 @@".to_string(),
+                lines: vec![line('-', "a"), line('+', "b")],
+            }],
+        }];
+
+        let cxml = generate_diff_cxml("base", "head", &files);
+        assert!(cxml.starts_with("<diff base=\"base\" head=\"head\">"));
+        assert!(cxml.contains("path=\"new.rs\" old_path=\"old.rs\" status=\"renamed\""));
+        assert!(cxml.contains("<hunk header=\"@@ -1 +1 @@\">\n-a\n+b\n</hunk>"));
+
+        let files = vec![FileDiff {
+            old_path: Some("a&b.rs".to_string()),
+            new_path: Some("say \"hi\".rs".to_string()),
+            hunks: vec![DiffHunk {
+                header: "@@ -1 +1 @@ fn f<T>()".to_string(),
+                lines: Vec::new(),
+            }],
+            ..files.into_iter().next().unwrap()
+        }];
+        let cxml = generate_diff_cxml("base", "head", &files);
+        assert!(cxml.contains("path=\"say &quot;hi&quot;.rs\" old_path=\"a&amp;b.rs\""));
+        assert!(cxml.contains("<hunk header=\"@@ -1 +1 @@ fn f&lt;T&gt;()\">"));
+    }
 }
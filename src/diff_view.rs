@@ -0,0 +1,300 @@
+use crate::file_analyzer::ScanOptions;
+use crate::git_ops::{self, GitRef};
+use anyhow::{Context, Result};
+use git2::{Commit, Delta, DiffFindOptions, DiffOptions, Patch, Repository};
+use std::str::FromStr;
+
+/// Revisions to compare with `--diff base..head`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffRange {
+    pub base: String,
+    pub head: String,
+}
+
+impl FromStr for DiffRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once("..") {
+            Some((base, head)) if !base.is_empty() && !head.starts_with('.') => Ok(Self {
+                base: base.to_string(),
+                // `base..` means up to HEAD, as in git
+                head: if head.is_empty() { "HEAD".to_string() } else { head.to_string() },
+            }),
+            _ => Err("expected <base>..<head>, e.g. main..feature or v1.0..HEAD".to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for DiffRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.base, self.head)
+    }
+}
+
+/// How file diffs are laid out on the page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DiffLayout {
+    /// One column with removed and added lines interleaved
+    #[default]
+    Unified,
+    /// Old and new versions next to each other
+    Split,
+}
+
+/// One line of a hunk
+#[derive(Debug, Clone)]
+pub struct DiffLine {
+    pub origin: char, // '+' | '-' | ' '
+    pub old_lineno: Option<u32>,
+    pub new_lineno: Option<u32>,
+    pub content: String,
+}
+
+/// A hunk with its `@@ ... @@` header
+#[derive(Debug, Clone)]
+pub struct DiffHunk {
+    pub header: String,
+    pub lines: Vec<DiffLine>,
+}
+
+/// The changes to one file between two revisions
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    pub status: String, // "added" | "deleted" | "modified" | "renamed" | "copied" | "typechange"
+    pub additions: usize,
+    pub deletions: usize,
+    pub note: Option<String>, // why no hunks are shown, e.g. "binary"
+    pub hunks: Vec<DiffHunk>,
+}
+
+impl FileDiff {
+    /// Path the file has after the change (before it, for deletions)
+    pub fn path(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// `old → new` for renames and copies, otherwise the path
+    pub fn display_path(&self) -> String {
+        match (&self.old_path, &self.new_path) {
+            (Some(old), Some(new)) if old != new => format!("{} → {}", old, new),
+            _ => self.path().to_string(),
+        }
+    }
+}
+
+/// Resolve a revision for `--diff`: any revspec, or a branch that only
+/// exists as a remote-tracking ref in a fresh clone
+pub fn resolve_rev<'r>(repo: &'r Repository, rev: &str) -> Result<Commit<'r>> {
+    git_ops::resolve_commit(repo, &GitRef::Commit(rev.to_string()))
+        .or_else(|_| git_ops::resolve_commit(repo, &GitRef::Branch(rev.to_string())))
+        .with_context(|| format!("Failed to find revision '{}'", rev))
+}
+
+/// Diff two commits file by file, detecting renames. `options` scopes the
+/// diff (subdir, include/exclude) and files over `max_bytes` get no hunks.
+pub fn collect_diff(
+    repo: &Repository,
+    base: &Commit,
+    head: &Commit,
+    options: &ScanOptions,
+) -> Result<Vec<FileDiff>> {
+    let base_tree = base.tree().context("Failed to read base tree")?;
+    let head_tree = head.tree().context("Failed to read head tree")?;
+
+    let mut diff_options = DiffOptions::new();
+    if let Some(subdir) = &options.subdir {
+        diff_options.pathspec(subdir);
+    }
+    let mut diff = repo
+        .diff_tree_to_tree(Some(&base_tree), Some(&head_tree), Some(&mut diff_options))
+        .context("Failed to diff revisions")?;
+
+    let mut find_options = DiffFindOptions::new();
+    find_options.renames(true).copies(true);
+    diff.find_similar(Some(&mut find_options))
+        .context("Failed to detect renames")?;
+
+    // Tree diffs leave `DiffFile::size` at 0, so sizes come from the object headers
+    let odb = repo.odb().context("Failed to open object database")?;
+    let blob_size = |file: git2::DiffFile| -> u64 {
+        if file.id().is_zero() {
+            return 0;
+        }
+        odb.read_header(file.id()).map(|(size, _)| size as u64).unwrap_or(0)
+    };
+
+    let mut files = Vec::new();
+    for (index, delta) in diff.deltas().enumerate() {
+        let path_of = |file: git2::DiffFile| file.path().map(|p| p.to_string_lossy().replace('\\', "/"));
+        let status = match delta.status() {
+            Delta::Added => "added",
+            Delta::Deleted => "deleted",
+            Delta::Modified => "modified",
+            Delta::Renamed => "renamed",
+            Delta::Copied => "copied",
+            Delta::Typechange => "typechange",
+            _ => continue,
+        };
+        let mut file = FileDiff {
+            old_path: (delta.status() != Delta::Added).then(|| path_of(delta.old_file())).flatten(),
+            new_path: (delta.status() != Delta::Deleted).then(|| path_of(delta.new_file())).flatten(),
+            status: status.to_string(),
+            additions: 0,
+            deletions: 0,
+            note: None,
+            hunks: Vec::new(),
+        };
+        if !options.filter.allows(file.path()) {
+            continue;
+        }
+
+        let too_large = [delta.old_file(), delta.new_file()]
+            .into_iter()
+            .any(|f| blob_size(f) > options.max_bytes as u64);
+        if too_large {
+            file.note = Some("too large".to_string());
+            files.push(file);
+            continue;
+        }
+
+        let Some(patch) = Patch::from_diff(&diff, index).context("Failed to build patch")? else {
+            files.push(file);
+            continue;
+        };
+        if patch.delta().flags().is_binary() {
+            file.note = Some("binary".to_string());
+            files.push(file);
+            continue;
+        }
+
+        let (_, additions, deletions) = patch.line_stats().context("Failed to count lines")?;
+        file.additions = additions;
+        file.deletions = deletions;
+        for h in 0..patch.num_hunks() {
+            let (hunk, line_count) = patch.hunk(h).context("Failed to read hunk")?;
+            let mut lines = Vec::with_capacity(line_count);
+            for l in 0..line_count {
+                let line = patch.line_in_hunk(h, l).context("Failed to read hunk line")?;
+                // Skip "\ No newline at end of file" and similar markers
+                if !matches!(line.origin(), '+' | '-' | ' ') {
+                    continue;
+                }
+                lines.push(DiffLine {
+                    origin: line.origin(),
+                    old_lineno: line.old_lineno(),
+                    new_lineno: line.new_lineno(),
+                    content: String::from_utf8_lossy(line.content()).into_owned(),
+                });
+            }
+            file.hunks.push(DiffHunk {
+                header: String::from_utf8_lossy(hunk.header()).trim_end().to_string(),
+                lines,
+            });
+        }
+        files.push(file);
+    }
+
+    files.sort_by(|a, b| a.path().cmp(b.path()));
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_diff_range_parsing() {
+        let range: DiffRange = "main..feature".parse().unwrap();
+        assert_eq!((range.base.as_str(), range.head.as_str()), ("main", "feature"));
+        assert_eq!("v1.0..".parse::<DiffRange>().unwrap().head, "HEAD");
+        assert!("main".parse::<DiffRange>().is_err());
+        assert!("a...b".parse::<DiffRange>().is_err());
+    }
+
+    #[test]
+    fn test_collect_diff_with_rename() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let body = "fn shared() {}\n".repeat(20);
+        let repo = crate::git_ops::tests::init_repo_with_commits(
+            root,
+            &[("a.rs", "one\ntwo\nthree\n"), ("old.rs", body.as_str()), ("gone.txt", "bye\n")],
+        );
+        let base = repo.head().unwrap().peel_to_commit().unwrap();
+
+        // Modify a.rs, rename old.rs to new.rs, delete gone.txt
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let mut index = repo.index().unwrap();
+        std::fs::write(root.join("a.rs"), "one\n2\nthree\nfour\n").unwrap();
+        std::fs::rename(root.join("old.rs"), root.join("new.rs")).unwrap();
+        std::fs::remove_file(root.join("gone.txt")).unwrap();
+        for path in ["a.rs", "new.rs"] {
+            index.add_path(Path::new(path)).unwrap();
+        }
+        for path in ["old.rs", "gone.txt"] {
+            index.remove_path(Path::new(path)).unwrap();
+        }
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "change", &tree, &[&base]).unwrap();
+
+        let options = ScanOptions {
+            max_bytes: 10_000,
+            ..ScanOptions::for_tests()
+        };
+        let range: DiffRange = "HEAD~1..HEAD".parse().unwrap();
+        let base = resolve_rev(&repo, &range.base).unwrap();
+        let head = resolve_rev(&repo, &range.head).unwrap();
+        let files = collect_diff(&repo, &base, &head, &options).unwrap();
+
+        let summary: Vec<_> = files
+            .iter()
+            .map(|f| (f.display_path(), f.status.as_str(), f.additions, f.deletions))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("a.rs".to_string(), "modified", 2, 1),
+                ("gone.txt".to_string(), "deleted", 0, 1),
+                ("old.rs → new.rs".to_string(), "renamed", 0, 0),
+            ]
+        );
+
+        let lines: Vec<_> = files[0].hunks[0].lines.iter().map(|l| format!("{}{}", l.origin, l.content)).collect();
+        assert_eq!(lines, vec![" one\n", "-two\n", "+2\n", " three\n", "+four\n"]);
+    }
+
+    #[test]
+    fn test_collect_diff_skips_large_blobs() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let repo = crate::git_ops::tests::init_repo_with_commits(root, &[("small.txt", "a\n")]);
+        let base = repo.head().unwrap().peel_to_commit().unwrap();
+
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let mut index = repo.index().unwrap();
+        std::fs::write(root.join("small.txt"), "b\n").unwrap();
+        std::fs::write(root.join("big.txt"), "line\n".repeat(1000)).unwrap();
+        index.add_path(Path::new("small.txt")).unwrap();
+        index.add_path(Path::new("big.txt")).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let head_id = repo.commit(Some("HEAD"), &sig, &sig, "grow", &tree, &[&base]).unwrap();
+        let head = repo.find_commit(head_id).unwrap();
+
+        let options = ScanOptions::for_tests();
+        let files = collect_diff(&repo, &base, &head, &options).unwrap();
+
+        assert_eq!(files[0].path(), "big.txt");
+        assert_eq!(files[0].note.as_deref(), Some("too large"));
+        assert!(files[0].hunks.is_empty());
+        assert_eq!(files[1].path(), "small.txt");
+        assert_eq!((files[1].additions, files[1].deletions), (1, 1));
+    }
+}
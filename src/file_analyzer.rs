@@ -1,5 +1,10 @@
+use crate::ignore_rules::IgnoreRules;
+use crate::utils::bytes_human;
 use anyhow::{Context, Result};
+use git2::{ObjectType, Oid, Repository, Tree};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
 use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::HashSet;
 use std::fs;
 use std::io::Read;
 use std::path::{Path, PathBuf};
@@ -17,19 +22,238 @@ pub const MARKDOWN_EXTENSIONS: &[&str] = &[".md", ".markdown", ".mdown", ".mkd",
 #[derive(Debug, Clone)]
 pub struct RenderDecision {
     pub include: bool,
-    pub reason: String, // "ok" | "binary" | "too_large" | "ignored"
+    pub reason: String, // "ok" | "binary" | "too_large" | "ignored" | "gitignored" | "excluded_by_pattern" | "symlink" | "deleted" | "not_fetched"
+}
+
+impl RenderDecision {
+    /// Short human-readable render status, e.g. "rendered" or "too large"
+    pub fn status(&self) -> &str {
+        match self.reason.as_str() {
+            "ok" => "rendered",
+            "too_large" => "too large",
+            "ignored" | "gitignored" => "ignored",
+            "excluded_by_pattern" => "excluded",
+            "not_fetched" => "not fetched",
+            other => other,
+        }
+    }
+
+    /// A file listed by `--changes` that no longer exists
+    pub fn is_deleted(&self) -> bool {
+        self.reason == "deleted"
+    }
+
+    /// A blob left out of a partial clone, whose size is unknown
+    pub fn is_not_fetched(&self) -> bool {
+        self.reason == "not_fetched"
+    }
+}
+
+/// How many files were rendered and skipped. Deleted files (from `--changes`)
+/// are counted on their own and are not part of the total.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileCounts {
+    pub total: usize,
+    pub rendered: usize,
+    pub skipped: usize,
+    pub deleted: usize,
+}
+
+impl FileCounts {
+    pub fn of(file_infos: &[FileInfo]) -> Self {
+        let mut counts = FileCounts::default();
+        for info in file_infos {
+            if info.decision.include {
+                counts.rendered += 1;
+            } else if info.decision.is_deleted() {
+                counts.deleted += 1;
+            } else {
+                counts.skipped += 1;
+            }
+        }
+        counts.total = counts.rendered + counts.skipped;
+        counts
+    }
+
+    pub fn add(&mut self, other: FileCounts) {
+        self.total += other.total;
+        self.rendered += other.rendered;
+        self.skipped += other.skipped;
+        self.deleted += other.deleted;
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct FileInfo {
-    pub path: PathBuf,      // absolute path on disk
+    pub path: PathBuf,      // absolute path on disk (repo-relative for git tree entries)
     pub rel: String,        // path relative to repo root (slash-separated)
     pub size: u64,
+    pub contents: Option<String>, // set when read from git objects rather than disk
+    pub symlink_target: Option<String>, // set for symlinks that are listed rather than followed
     pub decision: RenderDecision,
 }
 
+impl FileInfo {
+    /// Read the file's text, from memory if it came from a git tree
+    pub fn read_to_string(&self) -> Result<String> {
+        match &self.contents {
+            Some(contents) => Ok(contents.clone()),
+            None => fs::read_to_string(&self.path)
+                .with_context(|| format!("Failed to read {}", self.path.display())),
+        }
+    }
+
+    /// Human-readable size, or "size unknown" for a blob that was not fetched
+    pub fn size_human(&self) -> String {
+        if self.decision.is_not_fetched() {
+            "size unknown".to_string()
+        } else {
+            bytes_human(self.size)
+        }
+    }
+}
+
+/// A file at `rel` for tests, rendered when `reason` is "ok"
+#[cfg(test)]
+pub(crate) fn file(rel: &str, size: u64, reason: &str) -> FileInfo {
+    FileInfo {
+        path: PathBuf::from(rel),
+        rel: rel.to_string(),
+        size,
+        contents: None,
+        symlink_target: None,
+        decision: RenderDecision {
+            include: reason == "ok",
+            reason: reason.to_string(),
+        },
+    }
+}
+
+/// Like `file`, with its contents held in memory
+#[cfg(test)]
+pub(crate) fn text_file(rel: &str, contents: &str, reason: &str) -> FileInfo {
+    FileInfo {
+        contents: Some(contents.to_string()),
+        ..file(rel, contents.len() as u64, reason)
+    }
+}
+
+/// Include/exclude glob filters applied to repo-relative paths
+#[derive(Debug, Clone, Default)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: Option<GlobSet>,
+}
+
+impl PathFilter {
+    /// Compile the `--include`/`--exclude` patterns. A pattern without a `/`
+    /// is matched against the file name, so `*.rs` matches at any depth.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        Ok(Self {
+            include: build_globset(include)?,
+            exclude: build_globset(exclude)?,
+        })
+    }
+
+    /// Check whether a repo-relative path passes the filters
+    pub fn allows(&self, rel: &str) -> bool {
+        let name = rel.rsplit('/').next().unwrap_or(rel);
+        let matches = |set: &GlobSet| set.is_match(rel) || set.is_match(name);
+
+        if let Some(exclude) = &self.exclude {
+            if matches(exclude) {
+                return false;
+            }
+        }
+        match &self.include {
+            Some(include) => matches(include),
+            None => true,
+        }
+    }
+}
+
+fn build_globset(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = if pattern.contains('/') {
+            GlobBuilder::new(pattern).literal_separator(true).build()
+        } else {
+            Glob::new(pattern)
+        }
+        .with_context(|| format!("Invalid glob pattern '{}'", pattern))?;
+        builder.add(glob);
+    }
+    Ok(Some(builder.build()?))
+}
+
+/// What to do with symbolic links found while scanning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SymlinkPolicy {
+    /// Leave symlinks out entirely
+    #[default]
+    Skip,
+    /// List symlinks and their targets in the skipped section
+    List,
+    /// Render the targets of symlinks that stay inside the repository
+    Follow,
+}
+
+/// Options controlling which files are scanned and rendered
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    pub max_bytes: usize,
+    pub respect_ignore: bool,
+    pub filter: PathFilter,
+    pub subdir: Option<String>, // only scan this repo-relative directory
+    pub symlinks: SymlinkPolicy,
+}
+
+#[cfg(test)]
+impl ScanOptions {
+    /// Options for tests: a 1 KiB limit, ignore files respected, no filters,
+    /// the whole repository and symlinks skipped
+    pub(crate) fn for_tests() -> Self {
+        Self {
+            max_bytes: 1024,
+            respect_ignore: true,
+            filter: PathFilter::default(),
+            subdir: None,
+            symlinks: SymlinkPolicy::Skip,
+        }
+    }
+}
+
+/// Normalize a `--subdir` argument to a slash-separated repo-relative path
+pub fn normalize_subdir(subdir: &str) -> Result<Option<String>> {
+    let parts: Vec<&str> = subdir
+        .split(['/', '\\'])
+        .filter(|p| !p.is_empty() && *p != ".")
+        .collect();
+
+    if parts.contains(&"..") || Path::new(subdir).is_absolute() {
+        anyhow::bail!("--subdir must be a path inside the repository: {}", subdir);
+    }
+    Ok((!parts.is_empty()).then(|| parts.join("/")))
+}
+
 /// Check if a file looks binary based on extension or content
 fn looks_binary(path: &Path) -> bool {
+    let mut buffer = vec![0; 8192];
+    if let Ok(mut file) = fs::File::open(path) {
+        if let Ok(n) = file.read(&mut buffer) {
+            buffer.truncate(n);
+            return looks_binary_bytes(path, &buffer);
+        }
+    }
+    looks_binary_bytes(path, &[])
+}
+
+/// Check if content looks binary, given its path and leading bytes
+fn looks_binary_bytes(path: &Path, buffer: &[u8]) -> bool {
     // Check extension first
     if let Some(ext) = path.extension() {
         let ext_str = format!(".{}", ext.to_string_lossy().to_lowercase());
@@ -38,29 +262,46 @@ fn looks_binary(path: &Path) -> bool {
         }
     }
 
-    // Check file content using infer crate (magic bytes)
-    if let Ok(mut file) = fs::File::open(path) {
-        let mut buffer = vec![0; 8192];
-        if let Ok(n) = file.read(&mut buffer) {
-            buffer.truncate(n);
-            
-            // Check for null bytes
-            if buffer.contains(&0) {
-                return true;
-            }
+    let buffer = &buffer[..buffer.len().min(8192)];
 
-            // Try to decode as UTF-8
-            if std::str::from_utf8(&buffer).is_err() {
-                return true;
-            }
-        }
+    // Check for null bytes
+    if buffer.contains(&0) {
+        return true;
     }
 
-    false
+    // Try to decode as UTF-8
+    std::str::from_utf8(buffer).is_err()
+}
+
+/// Classify a file by its repo-relative path and size. The binary check is
+/// only run when the cheaper checks pass.
+fn classify(
+    rel: &str,
+    size: u64,
+    options: &ScanOptions,
+    is_binary: impl FnOnce() -> bool,
+) -> RenderDecision {
+    let reason = if rel.starts_with(".git/") || rel.contains("/.git/") {
+        // Ignore VCS directories
+        "ignored"
+    } else if !options.filter.allows(rel) {
+        "excluded_by_pattern"
+    } else if size > options.max_bytes as u64 {
+        "too_large"
+    } else if is_binary() {
+        "binary"
+    } else {
+        "ok"
+    };
+
+    RenderDecision {
+        include: reason == "ok",
+        reason: reason.to_string(),
+    }
 }
 
 /// Decide whether to render a file
-fn decide_file(path: &Path, repo_root: &Path, max_bytes: usize) -> Result<FileInfo> {
+pub(crate) fn decide_file(path: &Path, repo_root: &Path, options: &ScanOptions) -> Result<FileInfo> {
     let rel = path
         .strip_prefix(repo_root)
         .context("Failed to get relative path")?
@@ -71,95 +312,161 @@ fn decide_file(path: &Path, repo_root: &Path, max_bytes: usize) -> Result<FileIn
         .map(|m| m.len())
         .unwrap_or(0);
 
-    // Ignore VCS directories
-    if rel.starts_with(".git/") || rel.contains("/.git/") {
-        return Ok(FileInfo {
-            path: path.to_path_buf(),
-            rel,
-            size,
-            decision: RenderDecision {
-                include: false,
-                reason: "ignored".to_string(),
-            },
-        });
-    }
-
-    // Check size
-    if size > max_bytes as u64 {
-        return Ok(FileInfo {
-            path: path.to_path_buf(),
-            rel,
-            size,
-            decision: RenderDecision {
-                include: false,
-                reason: "too_large".to_string(),
-            },
-        });
-    }
-
-    // Check if binary
-    if looks_binary(path) {
-        return Ok(FileInfo {
-            path: path.to_path_buf(),
-            rel,
-            size,
-            decision: RenderDecision {
-                include: false,
-                reason: "binary".to_string(),
-            },
-        });
+    let decision = classify(&rel, size, options, || looks_binary(path));
+
+    Ok(FileInfo {
+        path: path.to_path_buf(),
+        rel,
+        size,
+        contents: None,
+        symlink_target: None,
+        decision,
+    })
+}
+
+/// Record a path excluded by .gitignore/.ignore rules. Ignored directories are
+/// not descended into and are listed once with a trailing slash.
+fn gitignored_entry(path: &Path, repo_root: &Path, is_dir: bool) -> Result<FileInfo> {
+    let mut rel = path
+        .strip_prefix(repo_root)
+        .context("Failed to get relative path")?
+        .to_string_lossy()
+        .replace('\\', "/");
+    if is_dir {
+        rel.push('/');
     }
 
+    let size = if is_dir {
+        0
+    } else {
+        fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+    };
+
     Ok(FileInfo {
         path: path.to_path_buf(),
         rel,
         size,
+        contents: None,
+        symlink_target: None,
         decision: RenderDecision {
-            include: true,
-            reason: "ok".to_string(),
+            include: false,
+            reason: "gitignored".to_string(),
         },
     })
 }
 
+/// Record a symlink that is listed rather than followed
+pub(crate) fn symlink_entry(path: PathBuf, rel: String, target: String) -> FileInfo {
+    FileInfo {
+        path,
+        rel,
+        size: 0,
+        contents: None,
+        symlink_target: Some(target),
+        decision: RenderDecision {
+            include: false,
+            reason: "symlink".to_string(),
+        },
+    }
+}
+
+/// Where a symlink on disk leads when following it
+enum DiskLink {
+    File,
+    Dir(PathBuf), // canonical path of the target directory
+    Refused(&'static str),
+}
+
+/// Resolve a symlink for `--symlinks follow`. Targets must stay inside the
+/// repository, and a directory that is already being walked is a cycle.
+fn follow_disk_link(path: &Path, ancestors: &[PathBuf]) -> DiskLink {
+    let Ok(target) = path.canonicalize() else {
+        return DiskLink::Refused("target does not exist");
+    };
+    if !ancestors.first().map(|root| target.starts_with(root)).unwrap_or(false) {
+        return DiskLink::Refused("target escapes the repository root");
+    }
+
+    if target.is_dir() {
+        if ancestors.contains(&target) {
+            return DiskLink::Refused("target creates a cycle");
+        }
+        DiskLink::Dir(target)
+    } else {
+        DiskLink::File
+    }
+}
+
 /// Recursively collect all files in a repository
-pub fn collect_files(repo_root: &Path, max_bytes: usize, show_progress: bool) -> Result<Vec<FileInfo>> {
+pub fn collect_files(
+    repo_root: &Path,
+    options: &ScanOptions,
+    show_progress: bool,
+) -> Result<Vec<FileInfo>> {
     let mut infos = Vec::new();
+    let (start, mut rules) = scan_start(repo_root, options)?;
 
     // First pass: count total files for progress bar
     let total_files = if show_progress {
-        count_files(repo_root)?
+        count_files(repo_root, options)?
     } else {
         0
     };
 
-    let progress = if show_progress && total_files > 0 {
-        let pb = ProgressBar::new(total_files as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files ({msg})")
-                .unwrap()
-                .progress_chars("#>-")
-        );
-        Some(pb)
-    } else {
-        None
-    };
+    let progress = new_progress(total_files);
 
+    #[allow(clippy::too_many_arguments)]
     fn visit_dirs(
         dir: &Path,
         repo_root: &Path,
-        max_bytes: usize,
+        options: &ScanOptions,
+        rules: &mut IgnoreRules,
+        ancestors: &mut Vec<PathBuf>,
         infos: &mut Vec<FileInfo>,
         progress: &Option<ProgressBar>,
     ) -> Result<()> {
         if dir.is_dir() {
+            let pushed = rules.push_dir(dir);
             for entry in fs::read_dir(dir)? {
                 let entry = entry?;
                 let path = entry.path();
                 
-                // Skip symlinks
                 if path.is_symlink() {
-                    continue;
+                    let rel = path
+                        .strip_prefix(repo_root)
+                        .context("Failed to get relative path")?
+                        .to_string_lossy()
+                        .replace('\\', "/");
+                    let target = fs::read_link(&path)
+                        .map(|t| t.to_string_lossy().to_string())
+                        .unwrap_or_default();
+
+                    match options.symlinks {
+                        SymlinkPolicy::Skip => continue,
+                        SymlinkPolicy::List => {
+                            report_progress(progress, &rel);
+                            infos.push(symlink_entry(path, rel, target));
+                            continue;
+                        }
+                        SymlinkPolicy::Follow => match follow_disk_link(&path, ancestors) {
+                            DiskLink::Dir(canonical) => {
+                                if rules.is_ignored(&path, true) {
+                                    continue;
+                                }
+                                ancestors.push(canonical);
+                                visit_dirs(&path, repo_root, options, rules, ancestors, infos, progress)?;
+                                ancestors.pop();
+                                continue;
+                            }
+                            DiskLink::File => {}
+                            DiskLink::Refused(why) => {
+                                eprintln!("⚠️  Not following symlink {} -> {}: {}", rel, target, why);
+                                report_progress(progress, &rel);
+                                infos.push(symlink_entry(path, rel, target));
+                                continue;
+                            }
+                        },
+                    }
                 }
 
                 if path.is_dir() {
@@ -167,28 +474,41 @@ pub fn collect_files(repo_root: &Path, max_bytes: usize, show_progress: bool) ->
                     if path.file_name().map(|n| n == ".git").unwrap_or(false) {
                         continue;
                     }
-                    visit_dirs(&path, repo_root, max_bytes, infos, progress)?;
-                } else if path.is_file() {
-                    if let Ok(info) = decide_file(&path, repo_root, max_bytes) {
+                    if rules.is_ignored(&path, true) {
                         if let Some(pb) = progress {
                             pb.inc(1);
-                            let rel = info.rel.clone();
-                            let short_name = if rel.len() > 50 {
-                                format!("...{}", &rel[rel.len()-47..])
-                            } else {
-                                rel
-                            };
-                            pb.set_message(short_name);
                         }
+                        infos.push(gitignored_entry(&path, repo_root, true)?);
+                        continue;
+                    }
+                    ancestors.push(path.canonicalize()?);
+                    visit_dirs(&path, repo_root, options, rules, ancestors, infos, progress)?;
+                    ancestors.pop();
+                } else if path.is_file() {
+                    // A `.git` file links a submodule checkout to its repository
+                    if path.file_name().map(|n| n == ".git").unwrap_or(false) {
+                        continue;
+                    }
+                    let decided = if rules.is_ignored(&path, false) {
+                        gitignored_entry(&path, repo_root, false)
+                    } else {
+                        decide_file(&path, repo_root, options)
+                    };
+                    if let Ok(info) = decided {
+                        report_progress(progress, &info.rel);
                         infos.push(info);
                     }
                 }
             }
+            if pushed {
+                rules.pop();
+            }
         }
         Ok(())
     }
 
-    visit_dirs(repo_root, repo_root, max_bytes, &mut infos, &progress)?;
+    let mut ancestors = scan_ancestors(repo_root, &start)?;
+    visit_dirs(&start, repo_root, options, &mut rules, &mut ancestors, &mut infos, &progress)?;
     
     if let Some(pb) = progress {
         pb.finish_with_message("Complete");
@@ -200,34 +520,518 @@ pub fn collect_files(repo_root: &Path, max_bytes: usize, show_progress: bool) ->
     Ok(infos)
 }
 
+/// Collect exactly the listed files (`--files-from`). Paths are relative to
+/// the repository root, or absolute inside it, and each still goes through
+/// `decide_file`; .gitignore does not apply to files asked for by name.
+/// Missing paths, directories and paths outside the root are reported
+/// together as one error.
+pub fn collect_listed_files(
+    repo_root: &Path,
+    paths: &[String],
+    options: &ScanOptions,
+) -> Result<Vec<FileInfo>> {
+    let root = repo_root.canonicalize().context("Failed to resolve repository root")?;
+    let mut infos: Vec<FileInfo> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut problems = Vec::new();
+
+    for listed in paths {
+        let rel = match listed_rel_path(&root, listed) {
+            Ok(rel) => rel,
+            Err(why) => {
+                problems.push(format!("  {}: {}", listed, why));
+                continue;
+            }
+        };
+        let in_subdir = match &options.subdir {
+            Some(subdir) => rel.starts_with(&format!("{}/", subdir)),
+            None => true,
+        };
+        if !in_subdir || !seen.insert(rel.clone()) {
+            continue;
+        }
+
+        let path = repo_root.join(&rel);
+        if path.is_symlink() {
+            let target = fs::read_link(&path)
+                .map(|t| t.to_string_lossy().to_string())
+                .unwrap_or_default();
+            match options.symlinks {
+                SymlinkPolicy::Skip => continue,
+                SymlinkPolicy::List => {
+                    infos.push(symlink_entry(path, rel, target));
+                    continue;
+                }
+                SymlinkPolicy::Follow => match follow_disk_link(&path, std::slice::from_ref(&root)) {
+                    DiskLink::File => {}
+                    DiskLink::Dir(_) => {
+                        problems.push(format!("  {}: is a directory", listed));
+                        continue;
+                    }
+                    DiskLink::Refused(why) => {
+                        eprintln!("⚠️  Not following symlink {} -> {}: {}", rel, target, why);
+                        infos.push(symlink_entry(path, rel, target));
+                        continue;
+                    }
+                },
+            }
+        }
+        infos.push(decide_file(&path, repo_root, options)?);
+    }
+
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "{} listed path(s) cannot be rendered:\n{}",
+            problems.len(),
+            problems.join("\n")
+        );
+    }
+
+    infos.sort_by(|a, b| a.rel.cmp(&b.rel));
+    Ok(infos)
+}
+
+/// The repo-relative path of a listed file, or why it cannot be rendered.
+/// Only the parent directory is resolved, so a listed symlink keeps its own path.
+fn listed_rel_path(root: &Path, listed: &str) -> std::result::Result<String, &'static str> {
+    let joined = root.join(listed.trim_start_matches("./"));
+    let (Some(parent), Some(name)) = (joined.parent(), joined.file_name()) else {
+        return Err("not a file");
+    };
+    let parent = parent.canonicalize().map_err(|_| "not found")?;
+    let path = parent.join(name);
+    let rel = path.strip_prefix(root).map_err(|_| "outside the repository")?;
+
+    if !path.is_symlink() {
+        if !path.exists() {
+            return Err("not found");
+        }
+        if path.is_dir() {
+            return Err("is a directory");
+        }
+    }
+    if rel.components().next().map(|c| c.as_os_str() == ".git").unwrap_or(false) {
+        return Err("inside .git");
+    }
+    Ok(rel.to_string_lossy().replace('\\', "/"))
+}
+
+/// Collect all files from a git tree, reading blobs straight from the object
+/// database so the working directory is never touched
+pub fn collect_tree_files(
+    repo: &Repository,
+    tree: &Tree,
+    options: &ScanOptions,
+    show_progress: bool,
+) -> Result<Vec<FileInfo>> {
+    // Narrow to the subtree, keeping paths repo-relative
+    let scope = options.subdir.as_deref().unwrap_or("");
+    if !scope.is_empty() {
+        tree.get_path(Path::new(scope))
+            .ok()
+            .filter(|entry| entry.kind() == Some(ObjectType::Tree))
+            .with_context(|| format!("Subdirectory not found in repository: {}", scope))?;
+    }
+
+    collect_subtree(repo, tree, scope, "", options, show_progress)
+}
+
+/// Maximum number of symlinks resolved in a chain, as in Linux's ELOOP limit
+const MAX_SYMLINK_HOPS: usize = 40;
+
+/// A path found while walking a tree
+enum TreeItem {
+    Blob(String, Oid),
+    Symlink(String, String), // link path and target
+}
+
+/// Where a symlink in a tree leads when following it
+enum TreeLink<'r> {
+    Blob(Oid),
+    Tree(Tree<'r>, String), // target tree and its path in the root tree
+}
+
+/// Join a slash-separated path onto a directory that may be empty
+fn join_rel(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// Resolve a symlink target relative to the link's directory, returning
+/// None when `..` climbs above the root
+fn resolve_link_path(link: &str, target: &str) -> Option<String> {
+    let mut parts: Vec<&str> = link.split('/').collect();
+    parts.pop();
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            other => parts.push(other),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Resolve a symlink for `--symlinks follow` within `root`. Targets must stay
+/// inside the tree, and a directory that is already being walked is a cycle.
+fn follow_tree_link<'r>(
+    repo: &'r Repository,
+    root: &Tree<'r>,
+    link: &str,
+    target: &str,
+    ancestors: &[String],
+) -> std::result::Result<TreeLink<'r>, &'static str> {
+    let mut link = link.to_string();
+    let mut target = target.to_string();
+
+    for _ in 0..MAX_SYMLINK_HOPS {
+        if target.starts_with('/') {
+            return Err("target escapes the repository root");
+        }
+        let resolved = resolve_link_path(&link, &target).ok_or("target escapes the repository root")?;
+        if resolved.is_empty() {
+            return Err("target creates a cycle");
+        }
+
+        let entry = root.get_path(Path::new(&resolved)).map_err(|_| "target does not exist")?;
+        match entry.kind() {
+            Some(ObjectType::Tree) => {
+                if ancestors.contains(&resolved) {
+                    return Err("target creates a cycle");
+                }
+                let tree = repo.find_tree(entry.id()).map_err(|_| "target does not exist")?;
+                return Ok(TreeLink::Tree(tree, resolved));
+            }
+            Some(ObjectType::Blob) if entry.filemode() == 0o120000 => {
+                let blob = repo.find_blob(entry.id()).map_err(|_| "target does not exist")?;
+                target = String::from_utf8_lossy(blob.content()).into_owned();
+                link = resolved;
+            }
+            Some(ObjectType::Blob) => return Ok(TreeLink::Blob(entry.id())),
+            _ => return Err("target is not a file or directory"),
+        }
+    }
+    Err("too many levels of symbolic links")
+}
+
+/// Walk `tree`, found at `real` in the root tree and shown at `shown`,
+/// applying the symlink policy. Submodule gitlinks are left to the caller.
+#[allow(clippy::too_many_arguments)]
+fn walk_tree<'r>(
+    repo: &'r Repository,
+    root: &Tree<'r>,
+    tree: &Tree<'r>,
+    real: &str,
+    shown: &str,
+    policy: SymlinkPolicy,
+    ancestors: &mut Vec<String>,
+    items: &mut Vec<TreeItem>,
+) -> Result<()> {
+    ancestors.push(real.to_string());
+    for entry in tree.iter() {
+        let name = String::from_utf8_lossy(entry.name_bytes());
+        let real_path = join_rel(real, &name);
+        let shown_path = join_rel(shown, &name);
+
+        match entry.kind() {
+            Some(ObjectType::Tree) => {
+                let subtree = repo.find_tree(entry.id()).context("Failed to read tree")?;
+                walk_tree(repo, root, &subtree, &real_path, &shown_path, policy, ancestors, items)?;
+            }
+            Some(ObjectType::Blob) if entry.filemode() == 0o120000 => {
+                let target = repo
+                    .find_blob(entry.id())
+                    .map(|b| String::from_utf8_lossy(b.content()).into_owned())
+                    .unwrap_or_default();
+                match policy {
+                    SymlinkPolicy::Skip => {}
+                    SymlinkPolicy::List => items.push(TreeItem::Symlink(shown_path, target)),
+                    SymlinkPolicy::Follow => {
+                        match follow_tree_link(repo, root, &real_path, &target, ancestors) {
+                            Ok(TreeLink::Blob(oid)) => items.push(TreeItem::Blob(shown_path, oid)),
+                            Ok(TreeLink::Tree(subtree, target_real)) => walk_tree(
+                                repo, root, &subtree, &target_real, &shown_path, policy, ancestors, items,
+                            )?,
+                            Err(why) => {
+                                eprintln!("⚠️  Not following symlink {} -> {}: {}", shown_path, target, why);
+                                items.push(TreeItem::Symlink(shown_path, target));
+                            }
+                        }
+                    }
+                }
+            }
+            Some(ObjectType::Blob) => items.push(TreeItem::Blob(shown_path, entry.id())),
+            _ => {}
+        }
+    }
+    ancestors.pop();
+    Ok(())
+}
+
+/// Collect the files under `scope` (a directory of `tree`, or empty for all
+/// of it), prefixing each path with `prefix` (empty or ending in `/`). Used
+/// for subdirectories and submodules.
+pub fn collect_subtree(
+    repo: &Repository,
+    tree: &Tree,
+    scope: &str,
+    prefix: &str,
+    options: &ScanOptions,
+    show_progress: bool,
+) -> Result<Vec<FileInfo>> {
+    let scoped = if scope.is_empty() {
+        tree.clone()
+    } else {
+        tree.get_path(Path::new(scope))
+            .and_then(|entry| repo.find_tree(entry.id()))
+            .with_context(|| format!("Subdirectory not found in repository: {}", scope))?
+    };
+
+    // Every directory above the scope counts as being walked, so a link back
+    // up to one of them is a cycle
+    let mut ancestors = vec![String::new()];
+    let mut dir = String::new();
+    for part in scope.split('/').filter(|p| !p.is_empty()) {
+        dir = join_rel(&dir, part);
+        ancestors.push(dir.clone());
+    }
+    ancestors.pop();
+
+    // First pass: list blobs so the progress bar knows the total
+    let mut items = Vec::new();
+    walk_tree(repo, tree, &scoped, scope, scope, options.symlinks, &mut ancestors, &mut items)?;
+
+    let mut entries = Vec::new();
+    let mut infos = Vec::with_capacity(items.len());
+    for item in items {
+        match item {
+            TreeItem::Blob(rel, oid) => entries.push((format!("{}{}", prefix, rel), oid)),
+            TreeItem::Symlink(rel, target) => {
+                let rel = format!("{}{}", prefix, rel);
+                infos.push(symlink_entry(PathBuf::from(&rel), rel, target));
+            }
+        }
+    }
+
+    let progress = new_progress(if show_progress { entries.len() } else { 0 });
+    let odb = repo.odb().context("Failed to open object database")?;
+
+    for (rel, oid) in entries {
+        let info = blob_entry(repo, &odb, rel, oid, options)?;
+        report_progress(&progress, &info.rel);
+        infos.push(info);
+    }
+
+    if let Some(pb) = progress {
+        pb.finish_with_message("Complete");
+    }
+
+    infos.sort_by(|a, b| a.rel.cmp(&b.rel));
+    Ok(infos)
+}
+
+/// Decide whether to render the blob `oid`, stored at `rel`, loading its
+/// contents only when it will be rendered
+pub(crate) fn blob_entry(
+    repo: &Repository,
+    odb: &git2::Odb,
+    rel: String,
+    oid: Oid,
+    options: &ScanOptions,
+) -> Result<FileInfo> {
+    // Read only the header first so oversized blobs are never loaded
+    let path = PathBuf::from(&rel);
+    let size = match odb.read_header(oid) {
+        Ok((size, _)) => size,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            // Left out of a partial clone; its size is unknown without
+            // downloading it, so it is not counted as too large
+            return Ok(FileInfo {
+                path,
+                rel,
+                size: 0,
+                contents: None,
+                symlink_target: None,
+                decision: RenderDecision {
+                    include: false,
+                    reason: "not_fetched".to_string(),
+                },
+            });
+        }
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read object for {}", rel));
+        }
+    };
+
+    let mut blob = None;
+    let decision = classify(&rel, size as u64, options, || {
+        let loaded = repo.find_blob(oid).ok();
+        let binary = loaded
+            .as_ref()
+            .map(|b| looks_binary_bytes(&path, b.content()))
+            .unwrap_or(true);
+        blob = loaded;
+        binary
+    });
+
+    let contents = match (&blob, decision.include) {
+        (Some(b), true) => Some(String::from_utf8_lossy(b.content()).into_owned()),
+        _ => None,
+    };
+
+    Ok(FileInfo {
+        path,
+        rel,
+        size: size as u64,
+        contents,
+        symlink_target: None,
+        decision,
+    })
+}
+
+/// Create the scan progress bar, or None when there is nothing to show
+fn new_progress(total_files: usize) -> Option<ProgressBar> {
+    if total_files == 0 {
+        return None;
+    }
+
+    let pb = ProgressBar::new(total_files as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} files ({msg})")
+            .unwrap()
+            .progress_chars("#>-")
+    );
+    Some(pb)
+}
+
+/// Advance the progress bar and show the (shortened) current path
+fn report_progress(progress: &Option<ProgressBar>, rel: &str) {
+    if let Some(pb) = progress {
+        pb.inc(1);
+        let short_name = if rel.len() > 50 {
+            format!("...{}", &rel[rel.len()-47..])
+        } else {
+            rel.to_string()
+        };
+        pb.set_message(short_name);
+    }
+}
+
+/// Directory to start scanning from, with the ignore rules of every directory
+/// above it already loaded
+fn scan_start(repo_root: &Path, options: &ScanOptions) -> Result<(PathBuf, IgnoreRules)> {
+    let mut rules = IgnoreRules::new(repo_root, options.respect_ignore);
+    let Some(subdir) = &options.subdir else {
+        return Ok((repo_root.to_path_buf(), rules));
+    };
+
+    let start = repo_root.join(subdir);
+    if !start.is_dir() {
+        anyhow::bail!("Subdirectory not found in repository: {}", subdir);
+    }
+
+    let mut dir = repo_root.to_path_buf();
+    rules.push_dir(&dir);
+    for component in subdir.split('/') {
+        if rules.is_ignored(&dir.join(component), true) {
+            anyhow::bail!("Subdirectory is ignored by .gitignore: {}", subdir);
+        }
+        dir.push(component);
+        if dir != start {
+            rules.push_dir(&dir);
+        }
+    }
+    Ok((start, rules))
+}
+
+/// Canonical paths of the repository root and every directory down to
+/// `start`, the initial stack for symlink cycle detection
+fn scan_ancestors(repo_root: &Path, start: &Path) -> Result<Vec<PathBuf>> {
+    let root = repo_root.canonicalize().context("Failed to resolve repository root")?;
+    let mut ancestors = vec![root.clone()];
+    if let Ok(rest) = start.strip_prefix(repo_root) {
+        let mut dir = root;
+        for component in rest.components() {
+            dir.push(component);
+            ancestors.push(dir.clone());
+        }
+    }
+    Ok(ancestors)
+}
+
 /// Count total files in directory (for progress bar)
-fn count_files(dir: &Path) -> Result<usize> {
+fn count_files(repo_root: &Path, options: &ScanOptions) -> Result<usize> {
     let mut count = 0;
-    
-    fn count_recursive(dir: &Path, count: &mut usize) -> Result<()> {
+    let (dir, mut rules) = scan_start(repo_root, options)?;
+
+    fn count_recursive(
+        dir: &Path,
+        options: &ScanOptions,
+        rules: &mut IgnoreRules,
+        ancestors: &mut Vec<PathBuf>,
+        count: &mut usize,
+    ) -> Result<()> {
         if dir.is_dir() {
+            let pushed = rules.push_dir(dir);
             for entry in fs::read_dir(dir)? {
                 let entry = entry?;
                 let path = entry.path();
                 
                 if path.is_symlink() {
-                    continue;
+                    match options.symlinks {
+                        SymlinkPolicy::Skip => continue,
+                        SymlinkPolicy::List => {
+                            *count += 1;
+                            continue;
+                        }
+                        SymlinkPolicy::Follow => match follow_disk_link(&path, ancestors) {
+                            DiskLink::Dir(canonical) => {
+                                if !rules.is_ignored(&path, true) {
+                                    ancestors.push(canonical);
+                                    count_recursive(&path, options, rules, ancestors, count)?;
+                                    ancestors.pop();
+                                }
+                                continue;
+                            }
+                            DiskLink::File => {}
+                            DiskLink::Refused(_) => {
+                                *count += 1;
+                                continue;
+                            }
+                        },
+                    }
                 }
                 
                 if path.is_dir() {
                     if path.file_name().map(|n| n == ".git").unwrap_or(false) {
                         continue;
                     }
-                    count_recursive(&path, count)?;
-                } else if path.is_file() {
+                    if rules.is_ignored(&path, true) {
+                        *count += 1;
+                        continue;
+                    }
+                    ancestors.push(path.canonicalize()?);
+                    count_recursive(&path, options, rules, ancestors, count)?;
+                    ancestors.pop();
+                } else if path.is_file() && path.file_name().map(|n| n != ".git").unwrap_or(true) {
                     *count += 1;
                 }
             }
+            if pushed {
+                rules.pop();
+            }
         }
         Ok(())
     }
-    
-    count_recursive(dir, &mut count)?;
+
+    let mut ancestors = scan_ancestors(repo_root, &dir)?;
+    count_recursive(&dir, options, &mut rules, &mut ancestors, &mut count)?;
     Ok(count)
 }
 
@@ -241,4 +1045,230 @@ mod tests {
         assert!(BINARY_EXTENSIONS.contains(&".png"));
         assert!(BINARY_EXTENSIONS.contains(&".exe"));
     }
+
+    #[test]
+    fn test_collect_files_respects_gitignore() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::write(root.join("target/debug/out.txt"), "build output").unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("run.log"), "log").unwrap();
+
+        let mut options = ScanOptions::for_tests();
+        let infos = collect_files(root, &options, false).unwrap();
+        let reason = |rel: &str| {
+            infos
+                .iter()
+                .find(|f| f.rel == rel)
+                .map(|f| f.decision.reason.clone())
+        };
+        assert_eq!(reason("main.rs").as_deref(), Some("ok"));
+        assert_eq!(reason("run.log").as_deref(), Some("gitignored"));
+        assert_eq!(reason("target/").as_deref(), Some("gitignored"));
+        assert_eq!(reason("target/debug/out.txt"), None);
+
+        options.respect_ignore = false;
+        let infos = collect_files(root, &options, false).unwrap();
+        assert!(infos.iter().any(|f| f.rel == "target/debug/out.txt" && f.decision.include));
+    }
+
+    #[test]
+    fn test_path_filter() {
+        let filter = PathFilter::new(
+            &["src/**/*.rs".to_string(), "*.md".to_string()],
+            &["**/fixtures/**".to_string()],
+        )
+        .unwrap();
+        assert!(filter.allows("src/main.rs"));
+        assert!(filter.allows("src/a/b/lib.rs"));
+        assert!(filter.allows("docs/guide.md"));
+        assert!(!filter.allows("build.rs"));
+        assert!(!filter.allows("src/fixtures/data.rs"));
+        assert!(PathFilter::default().allows("anything/at/all.txt"));
+        assert!(PathFilter::new(&["[".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_collect_tree_files_reads_revision() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let repo = crate::git_ops::tests::init_repo_with_commits(
+            root,
+            &[("src/lib.rs", "old"), ("src/lib.rs", "new")],
+        );
+        fs::write(root.join("src/lib.rs"), "uncommitted").unwrap();
+
+        let options = ScanOptions::for_tests();
+        let first = repo.revparse_single("HEAD~1").unwrap().peel_to_commit().unwrap();
+        let infos = collect_tree_files(&repo, &first.tree().unwrap(), &options, false).unwrap();
+
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].rel, "src/lib.rs");
+        assert_eq!(infos[0].read_to_string().unwrap(), "old");
+        assert_eq!(fs::read_to_string(root.join("src/lib.rs")).unwrap(), "uncommitted");
+    }
+
+    #[test]
+    fn test_collect_tree_files_partial_clone() {
+        // Partial clones need the git command; skip where it is missing
+        if std::process::Command::new("git").arg("--version").output().is_err() {
+            eprintln!("skipping: git is not available");
+            return;
+        }
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        let big = "x".repeat(4096);
+        let repo = crate::git_ops::tests::init_repo_with_commits(
+            &source,
+            &[("small.txt", "small"), ("big.txt", big.as_str())],
+        );
+        repo.config().unwrap().set_bool("uploadpack.allowFilter", true).unwrap();
+
+        let dest = temp_dir.path().join("partial");
+        let url = format!("file://{}", source.display());
+        let clone_options = crate::git_ops::CloneOptions {
+            shallow: true,
+            sparse_path: None,
+            blob_limit: Some(1024),
+            parent_url: None,
+        };
+        crate::git_ops::clone_bare(&url, &dest, &crate::git_ops::GitRef::Default, &clone_options)
+            .unwrap();
+
+        let options = ScanOptions::for_tests();
+        let bare = Repository::open(&dest).unwrap();
+        let tree = bare.head().unwrap().peel_to_tree().unwrap();
+        let big_oid = tree.get_name("big.txt").unwrap().id();
+        assert!(!bare.odb().unwrap().exists(big_oid));
+        let infos = collect_tree_files(&bare, &tree, &options, false).unwrap();
+
+        let reason = |rel: &str| infos.iter().find(|f| f.rel == rel).unwrap().decision.reason.clone();
+        assert_eq!(reason("small.txt"), "ok");
+        assert_eq!(reason("big.txt"), "not_fetched");
+        let big_info = infos.iter().find(|f| f.rel == "big.txt").unwrap();
+        assert_eq!(big_info.size_human(), "size unknown");
+    }
+
+    #[test]
+    fn test_subdir_scan_keeps_repo_relative_paths() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let repo = crate::git_ops::tests::init_repo_with_commits(
+            root,
+            &[("README.md", "readme"), ("crates/core/src/lib.rs", "lib"), ("crates/core/x.log", "log")],
+        );
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("crates/core/y.log"), "untracked log").unwrap();
+
+        let options = ScanOptions {
+            subdir: normalize_subdir("./crates/core/").unwrap(),
+            ..ScanOptions::for_tests()
+        };
+        let infos = collect_files(root, &options, false).unwrap();
+        let rels: Vec<_> = infos.iter().map(|f| (f.rel.as_str(), f.decision.include)).collect();
+        // x.log is tracked, so .gitignore does not apply to it
+        assert_eq!(
+            rels,
+            vec![("crates/core/src/lib.rs", true), ("crates/core/x.log", true), ("crates/core/y.log", false)]
+        );
+
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        let infos = collect_tree_files(&repo, &tree, &options, false).unwrap();
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[0].rel, "crates/core/src/lib.rs");
+
+        assert!(normalize_subdir("../outside").is_err());
+        assert_eq!(normalize_subdir(".").unwrap(), None);
+    }
+
+    #[test]
+    fn test_collect_listed_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("logo.png"), [0u8, 1, 2]).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("debug.log"), "listed, so rendered").unwrap();
+
+        let options = ScanOptions::for_tests();
+        let absolute = root.join("debug.log").to_string_lossy().to_string();
+        let listed: Vec<String> = ["./src/main.rs", "logo.png", absolute.as_str(), "src/main.rs"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let infos = collect_listed_files(root, &listed, &options).unwrap();
+        let summary: Vec<_> = infos.iter().map(|f| (f.rel.as_str(), f.decision.reason.as_str())).collect();
+        assert_eq!(summary, vec![("debug.log", "ok"), ("logo.png", "binary"), ("src/main.rs", "ok")]);
+
+        let bad: Vec<String> = ["missing.rs", "../outside.rs", "src"].iter().map(|s| s.to_string()).collect();
+        let err = collect_listed_files(root, &bad, &options).unwrap_err().to_string();
+        assert!(err.starts_with("3 listed path(s) cannot be rendered"));
+        assert!(err.contains("missing.rs: not found"));
+        assert!(err.contains("../outside.rs: outside the repository"));
+        assert!(err.contains("src: is a directory"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlink_policies() {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().join("repo");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/lib.rs"), "lib").unwrap();
+        fs::write(temp_dir.path().join("secret.txt"), "outside").unwrap();
+        symlink("src/lib.rs", root.join("lib.rs")).unwrap();
+        symlink("src", root.join("code")).unwrap();
+        symlink("..", root.join("src/up")).unwrap();
+        symlink("../secret.txt", root.join("secret")).unwrap();
+
+        let mut options = ScanOptions {
+            respect_ignore: false,
+            ..ScanOptions::for_tests()
+        };
+        let rels = |infos: &[FileInfo]| -> Vec<(String, String)> {
+            infos.iter().map(|f| (f.rel.clone(), f.decision.reason.clone())).collect()
+        };
+        let pairs = |expected: &[(&str, &str)]| -> Vec<(String, String)> {
+            expected.iter().map(|(a, b)| (a.to_string(), b.to_string())).collect()
+        };
+
+        let infos = collect_files(&root, &options, false).unwrap();
+        assert_eq!(rels(&infos), pairs(&[("src/lib.rs", "ok")]));
+
+        options.symlinks = SymlinkPolicy::List;
+        let infos = collect_files(&root, &options, false).unwrap();
+        assert_eq!(infos.len(), 5);
+        let link = infos.iter().find(|f| f.rel == "code").unwrap();
+        assert_eq!(link.decision.reason, "symlink");
+        assert_eq!(link.symlink_target.as_deref(), Some("src"));
+
+        // The cycle and the escape are listed, everything else is rendered
+        options.symlinks = SymlinkPolicy::Follow;
+        let expected = pairs(&[
+            ("code/lib.rs", "ok"),
+            ("code/up", "symlink"),
+            ("lib.rs", "ok"),
+            ("secret", "symlink"),
+            ("src/lib.rs", "ok"),
+            ("src/up", "symlink"),
+        ]);
+        let infos = collect_files(&root, &options, false).unwrap();
+        assert_eq!(rels(&infos), expected);
+
+        // The same links read from git objects
+        let repo = Repository::init(&root).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let infos = collect_tree_files(&repo, &tree, &options, false).unwrap();
+        assert_eq!(rels(&infos), expected);
+        assert_eq!(infos[2].read_to_string().unwrap(), "lib");
+    }
 }
@@ -1,70 +1,487 @@
+use crate::credentials::Authenticator;
 use anyhow::{Context, Result};
-use git2::{Repository, build::RepoBuilder};
+use git2::{Commit, FetchOptions, Repository, build::{CheckoutBuilder, RepoBuilder}};
 use std::path::Path;
+use std::process::{Command, Stdio};
 
 #[derive(Debug, Clone)]
 pub enum GitRef {
     Branch(String),
     Tag(String),
     Commit(String),
+    /// A GitHub pull request or GitLab merge request, by number
+    PullRequest(u64),
     Default,
 }
 
+/// Local ref that fetched pull/merge request heads are stored under
+fn pull_request_ref(number: u64) -> String {
+    format!("refs/easycopy/pr/{}", number)
+}
+
+/// Refspecs for a pull request head: GitHub's `refs/pull`, then GitLab's
+/// `refs/merge-requests`
+fn pull_request_refspecs(number: u64) -> [String; 2] {
+    let local = pull_request_ref(number);
+    [
+        format!("+refs/pull/{}/head:{}", number, local),
+        format!("+refs/merge-requests/{}/head:{}", number, local),
+    ]
+}
+
+/// Options controlling how much of a remote is downloaded and checked out
+#[derive(Debug, Clone, Default)]
+pub struct CloneOptions {
+    /// Fetch only the requested commit (depth 1)
+    pub shallow: bool,
+    /// Only check out this repo-relative directory (sparse checkout)
+    pub sparse_path: Option<String>,
+    /// Skip downloading blobs larger than this many bytes (bare clones only)
+    pub blob_limit: Option<u64>,
+    /// Repository whose `.gitmodules` named this remote; `EASYCOPY_TOKEN` is
+    /// only sent when both are on the same host
+    pub parent_url: Option<String>,
+}
+
 /// Clone a repository to the specified destination with optional ref specification
-pub fn clone_repo(url: &str, dest: &Path, git_ref: &GitRef) -> Result<()> {
+pub fn clone_repo(url: &str, dest: &Path, git_ref: &GitRef, options: &CloneOptions) -> Result<()> {
+    clone_at(url, dest, git_ref, options, false)?;
+    Ok(())
+}
+
+/// Clone a repository without a working tree. The requested ref is resolved
+/// afterwards with `resolve_commit` and read straight from the object database.
+///
+/// With `blob_limit`, blobs larger than the limit are filtered out on the
+/// server. libgit2 has no partial clone support, so this goes through the
+/// `git` command when available and falls back to a regular clone otherwise.
+pub fn clone_bare(url: &str, dest: &Path, git_ref: &GitRef, options: &CloneOptions) -> Result<()> {
+    if let Some(limit) = options.blob_limit {
+        match try_git_partial_clone(url, dest, git_ref, options.shallow, limit) {
+            Ok(()) => return Ok(()),
+            Err(e) => eprintln!("⚠️  Partial clone not possible, cloning all blobs instead: {:#}", e),
+        }
+        // Leave no half-written clone behind for the fallback
+        let _ = std::fs::remove_dir_all(dest);
+    }
+
+    clone_at(url, dest, git_ref, options, true)?;
+    Ok(())
+}
+
+/// Try a partial clone with the external `git` command. Commits are not
+/// supported by `git clone --branch`, so they always use libgit2.
+fn try_git_partial_clone(
+    url: &str,
+    dest: &Path,
+    git_ref: &GitRef,
+    shallow: bool,
+    blob_limit: u64,
+) -> Result<()> {
+    let mut cmd = Command::new("git");
+    cmd.arg("clone")
+        .arg("--quiet")
+        .arg("--bare")
+        // `blob:limit=N` omits blobs of N bytes or more
+        .arg(format!("--filter=blob:limit={}", blob_limit + 1))
+        // Never prompt on the terminal; libgit2 handles credentials in the fallback
+        .env("GIT_TERMINAL_PROMPT", "0");
+    if shallow {
+        cmd.arg("--depth").arg("1");
+    }
+    match git_ref {
+        GitRef::Branch(name) | GitRef::Tag(name) => {
+            cmd.arg("--branch").arg(name);
+        }
+        GitRef::Commit(_) | GitRef::PullRequest(_) => {
+            anyhow::bail!("git clone cannot check out a commit or pull request")
+        }
+        GitRef::Default => {}
+    }
+
+    let output = cmd
+        .arg("--")
+        .arg(url)
+        .arg(dest)
+        .stdin(Stdio::null())
+        .output()
+        .context("could not run git")?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("git clone --filter failed: {}", stderr.trim());
+    }
+    Ok(())
+}
+
+/// Create or update a bare cache clone of `url` at `dest` and fetch `git_ref`.
+///
+/// The clone mirrors branches and tags into `refs/heads/*` and `refs/tags/*`
+/// (like `git clone --bare`), so refs stay current across fetches and
+/// `resolve_commit` works on it directly.
+pub fn fetch_into_cache(url: &str, dest: &Path, git_ref: &GitRef, shallow: bool) -> Result<()> {
+    let repo = if dest.exists() {
+        Repository::open_bare(dest)
+            .with_context(|| format!("Cache entry {} is not a bare repository", dest.display()))?
+    } else {
+        let repo = Repository::init_bare(dest)
+            .context("Failed to initialise cache repository")?;
+        repo.remote_with_fetch("origin", url, "+refs/heads/*:refs/heads/*")
+            .context("Failed to add remote")?;
+        repo.remote_add_fetch("origin", "+refs/tags/*:refs/tags/*")
+            .context("Failed to add remote")?;
+        repo
+    };
+
+    let auth = Authenticator::from_env(url, None);
+    let shallow = shallow && fetches_shallow(git_ref) && !is_local_url(url);
+    let fetch_options = &mut fetch_options(&auth, shallow);
+    if !shallow && repo.is_shallow() {
+        // Deepen an entry left shallow by an earlier run; libgit2 reads the
+        // maximum depth as "unshallow"
+        fetch_options.depth(i32::MAX);
+    }
+
+    let mut remote = repo.find_remote("origin")
+        .context("Cache entry has no origin remote")?;
+
+    if let GitRef::PullRequest(number) = git_ref {
+        return fetch_pull_request(&repo, &mut remote, url, *number, &auth, fetch_options);
+    }
+
+    // Fetch only what was asked for; an empty list uses the configured refspecs
+    let refspecs: Vec<String> = match git_ref {
+        GitRef::Branch(branch) => vec![format!("+refs/heads/{0}:refs/heads/{0}", branch)],
+        GitRef::Tag(tag) => vec![format!("+refs/tags/{0}:refs/tags/{0}", tag)],
+        GitRef::Commit(commit_hash) if is_full_sha(commit_hash) => {
+            let present = git2::Oid::from_str(commit_hash)
+                .map(|oid| repo.find_commit(oid).is_ok())
+                .unwrap_or(false);
+            if present {
+                return Ok(());
+            }
+            vec![format!("+{}:refs/easycopy/target", commit_hash)]
+        }
+        GitRef::Commit(_) | GitRef::PullRequest(_) | GitRef::Default => Vec::new(),
+    };
+
+    auth.check(url, remote.fetch(&refspecs, Some(fetch_options), None))
+        .with_context(|| format!("Failed to fetch {}", url))?;
+
+    // Follow the remote's default branch, which may have changed
+    if let GitRef::Default = git_ref {
+        if let Ok(head) = remote.default_branch() {
+            if let Some(head) = head.as_str() {
+                repo.set_head(head).context("Failed to set HEAD")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fetch the head of pull request `number` into `pull_request_ref`, trying
+/// the GitHub and then the GitLab ref layout
+fn fetch_pull_request(
+    repo: &Repository,
+    remote: &mut git2::Remote<'_>,
+    url: &str,
+    number: u64,
+    auth: &Authenticator,
+    fetch_options: &mut FetchOptions<'_>,
+) -> Result<()> {
+    // A refspec whose source does not exist fetches nothing, so check the
+    // local ref after each attempt
+    for refspec in pull_request_refspecs(number) {
+        auth.check(url, remote.fetch(&[refspec.as_str()], Some(fetch_options), None))
+            .with_context(|| format!("Failed to fetch pull request #{} from {}", number, url))?;
+        if repo.find_reference(&pull_request_ref(number)).is_ok() {
+            return Ok(());
+        }
+    }
+    anyhow::bail!(
+        "Pull request #{} not found on {} (tried refs/pull/{}/head and refs/merge-requests/{}/head)",
+        number, url, number, number
+    )
+}
+
+/// Fetch options that authenticate with `auth`, limited to depth 1 when `shallow`
+fn fetch_options(auth: &Authenticator, shallow: bool) -> FetchOptions<'static> {
+    let mut fetch_options = auth.fetch_options();
+    if shallow {
+        fetch_options.depth(1);
+    }
+    fetch_options
+}
+
+/// Check whether `git_ref` can be resolved from a depth-1 fetch: branches,
+/// tags, pull requests and full commit ids can, while abbreviated ids,
+/// revspecs and the default branch's history need the full depth
+fn fetches_shallow(git_ref: &GitRef) -> bool {
+    match git_ref {
+        GitRef::Branch(_) | GitRef::Tag(_) | GitRef::PullRequest(_) => true,
+        GitRef::Commit(commit_hash) => is_full_sha(commit_hash),
+        GitRef::Default => false,
+    }
+}
+
+/// Check whether a URL is served by libgit2's local transport
+fn is_local_url(url: &str) -> bool {
+    if url.starts_with("file://") {
+        return true;
+    }
+    if url.contains("://") {
+        return false;
+    }
+    // Like git: a colon before the first slash makes it scp-style `host:path`,
+    // except for a Windows drive letter
+    match url.find(':') {
+        Some(1) => url.starts_with(|c: char| c.is_ascii_alphabetic()),
+        Some(colon) => url[..colon].contains('/'),
+        None => true,
+    }
+}
+
+/// Check whether a string is a full (unabbreviated) SHA-1 commit id
+fn is_full_sha(rev: &str) -> bool {
+    rev.len() == 40 && rev.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Clone `url` into `dest`, checking out `git_ref` unless `bare` is set
+fn clone_at(
+    url: &str,
+    dest: &Path,
+    git_ref: &GitRef,
+    options: &CloneOptions,
+    bare: bool,
+) -> Result<Repository> {
+    // libgit2's local transport cannot do shallow fetches, and local clones
+    // are cheap anyway
+    let shallow = options.shallow && !is_local_url(url);
+    let sparse_path = options.sparse_path.as_deref();
+    let auth = Authenticator::from_env(url, options.parent_url.as_deref());
+
     match git_ref {
         GitRef::Default => {
             // Simple clone
-            Repository::clone(url, dest)
-                .context("Failed to clone repository")?;
+            let mut builder = RepoBuilder::new();
+            builder.bare(bare)
+                .fetch_options(fetch_options(&auth, shallow))
+                .with_checkout(sparse_checkout(sparse_path));
+            auth.check(url, builder.clone(url, dest))
+                .context("Failed to clone repository")
         }
         GitRef::Branch(branch) => {
             // Clone specific branch
             let mut builder = RepoBuilder::new();
-            builder.branch(branch);
-            builder.clone(url, dest)
-                .with_context(|| format!("Failed to clone repository with branch '{}'", branch))?;
+            builder.bare(bare)
+                .branch(branch)
+                .fetch_options(fetch_options(&auth, shallow))
+                .with_checkout(sparse_checkout(sparse_path));
+            auth.check(url, builder.clone(url, dest))
+                .with_context(|| format!("Failed to clone repository with branch '{}'", branch))
+        }
+        GitRef::Tag(tag) if shallow => {
+            // Fetch just the tag into an empty repository
+            let reference = format!("refs/tags/{}", tag);
+            let refspec = format!("+{}:{}", reference, reference);
+            let repo = fetch_into_new(url, dest, &refspec, &auth, shallow, bare)
+                .with_context(|| format!("Failed to fetch tag '{}'", tag))?;
+            if !bare {
+                checkout_revision(&repo, &reference, sparse_path)
+                    .with_context(|| format!("Failed to find tag '{}'", tag))?;
+            }
+            Ok(repo)
+        }
+        GitRef::Commit(commit_hash) if shallow && is_full_sha(commit_hash) => {
+            // Servers may refuse to serve a commit that is not a ref tip,
+            // in which case fall back to a full clone
+            let refspec = format!("+{}:refs/easycopy/target", commit_hash);
+            match fetch_into_new(url, dest, &refspec, &auth, shallow, bare) {
+                Ok(repo) => {
+                    if !bare {
+                        checkout_revision(&repo, commit_hash, sparse_path)
+                            .with_context(|| format!("Failed to find commit '{}'", commit_hash))?;
+                    }
+                    Ok(repo)
+                }
+                Err(e) => {
+                    eprintln!("⚠️  Could not fetch commit {} alone, cloning the full history instead: {:#}", commit_hash, e);
+                    let _ = std::fs::remove_dir_all(dest);
+                    let full = CloneOptions { shallow: false, ..options.clone() };
+                    clone_at(url, dest, git_ref, &full, bare)
+                }
+            }
+        }
+        GitRef::PullRequest(number) => {
+            // Pull request heads are not advertised under refs/heads, so
+            // fetch them explicitly into an empty repository
+            let repo = if bare {
+                Repository::init_bare(dest)
+            } else {
+                Repository::init(dest)
+            }
+            .context("Failed to initialise repository")?;
+            {
+                let mut remote = repo.remote("origin", url)
+                    .context("Failed to add remote")?;
+                fetch_pull_request(&repo, &mut remote, url, *number, &auth, &mut fetch_options(&auth, shallow))?;
+            }
+            if !bare {
+                checkout_revision(&repo, &pull_request_ref(*number), sparse_path)
+                    .with_context(|| format!("Failed to check out pull request #{}", number))?;
+            }
+            Ok(repo)
         }
         GitRef::Tag(tag) => {
             // For tags, we need to clone then checkout
-            let repo = Repository::clone(url, dest)
+            let mut builder = RepoBuilder::new();
+            builder.bare(bare)
+                .fetch_options(fetch_options(&auth, false))
+                .with_checkout(sparse_checkout(sparse_path));
+            let repo = auth.check(url, builder.clone(url, dest))
                 .context("Failed to clone repository")?;
-            
-            let reference = format!("refs/tags/{}", tag);
-            
-            // Find and checkout the specific reference
-            let (object, reference_obj) = repo.revparse_ext(&reference)
-                .with_context(|| format!("Failed to find tag '{}'", tag))?;
-            
-            repo.checkout_tree(&object, None)
-                .context("Failed to checkout tree")?;
-            
-            match reference_obj {
-                Some(gref) => repo.set_head(gref.name().unwrap()),
-                None => repo.set_head_detached(object.id()),
-            }.context("Failed to set HEAD")?;
+            if !bare {
+                checkout_revision(&repo, &format!("refs/tags/{}", tag), sparse_path)
+                    .with_context(|| format!("Failed to find tag '{}'", tag))?;
+            }
+            Ok(repo)
         }
         GitRef::Commit(commit_hash) => {
             // For commits, clone then checkout
-            let repo = Repository::clone(url, dest)
+            let mut builder = RepoBuilder::new();
+            builder.bare(bare)
+                .fetch_options(fetch_options(&auth, false))
+                .with_checkout(sparse_checkout(sparse_path));
+            let repo = auth.check(url, builder.clone(url, dest))
                 .context("Failed to clone repository")?;
-            
-            // Find and checkout the specific commit
-            let (object, reference_obj) = repo.revparse_ext(commit_hash)
-                .with_context(|| format!("Failed to find commit '{}'", commit_hash))?;
-            
-            repo.checkout_tree(&object, None)
-                .context("Failed to checkout tree")?;
-            
-            match reference_obj {
-                Some(gref) => repo.set_head(gref.name().unwrap()),
-                None => repo.set_head_detached(object.id()),
-            }.context("Failed to set HEAD")?;
+
+            // Commits only reachable from unadvertised refs (pull requests,
+            // deleted branches) need to be asked for by id
+            let missing = git2::Oid::from_str(commit_hash)
+                .map(|oid| repo.find_commit(oid).is_err())
+                .unwrap_or(false);
+            if is_full_sha(commit_hash) && missing {
+                let refspec = format!("+{}:refs/easycopy/target", commit_hash);
+                let mut remote = repo.find_remote("origin")
+                    .context("Clone has no origin remote")?;
+                auth.check(url, remote.fetch(&[refspec.as_str()], Some(&mut fetch_options(&auth, false)), None))
+                    .with_context(|| format!("Failed to fetch commit '{}'", commit_hash))?;
+            }
+
+            if !bare {
+                checkout_revision(&repo, commit_hash, sparse_path)
+                    .with_context(|| format!("Failed to find commit '{}'", commit_hash))?;
+            }
+            Ok(repo)
+        }
+    }
+}
+
+/// Checkout options limited to `sparse_path` and the ignore files of its
+/// parent directories, so the rest of the repository is never written
+fn sparse_checkout(sparse_path: Option<&str>) -> CheckoutBuilder<'static> {
+    let mut checkout = CheckoutBuilder::new();
+    if let Some(path) = sparse_path {
+        checkout.path(path);
+
+        let mut parent = String::new();
+        for component in path.split('/') {
+            for name in [".gitignore", ".ignore"] {
+                checkout.path(format!("{}{}", parent, name));
+            }
+            parent.push_str(component);
+            parent.push('/');
         }
     }
+    checkout
+}
+
+/// Initialise a repository at `dest` and fetch a single refspec from `url`
+fn fetch_into_new(
+    url: &str,
+    dest: &Path,
+    refspec: &str,
+    auth: &Authenticator,
+    shallow: bool,
+    bare: bool,
+) -> Result<Repository> {
+    let repo = if bare {
+        Repository::init_bare(dest)
+    } else {
+        Repository::init(dest)
+    }
+    .context("Failed to initialise repository")?;
+
+    {
+        let mut remote = repo.remote("origin", url)
+            .context("Failed to add remote")?;
+        auth.check(url, remote.fetch(&[refspec], Some(&mut fetch_options(auth, shallow)), None))
+            .context("Failed to fetch from remote")?;
+    }
+    Ok(repo)
+}
+
+/// Find a revision, check it out (optionally just `sparse_path`) and point HEAD at it
+fn checkout_revision(repo: &Repository, spec: &str, sparse_path: Option<&str>) -> Result<()> {
+    let (object, reference_obj) = repo.revparse_ext(spec)?;
+
+    repo.checkout_tree(&object, Some(&mut sparse_checkout(sparse_path)))
+        .context("Failed to checkout tree")?;
+
+    match reference_obj {
+        Some(gref) => repo.set_head(gref.name().unwrap()),
+        None => repo.set_head_detached(object.id()),
+    }.context("Failed to set HEAD")?;
     Ok(())
 }
 
+/// Open an existing local repository
+pub fn open_repo(repo_path: &Path) -> Result<Repository> {
+    Repository::open(repo_path)
+        .with_context(|| format!("{} is not a git repository", repo_path.display()))
+}
+
+/// Resolve a git reference to a commit in an existing repository. Commits
+/// accept any revspec, such as `HEAD~5` or `v1.2^{commit}`.
+pub fn resolve_commit<'r>(repo: &'r Repository, git_ref: &GitRef) -> Result<Commit<'r>> {
+    // Branches may only exist as remote-tracking refs in a fresh clone
+    let (specs, what, name) = match git_ref {
+        GitRef::Branch(branch) => (
+            vec![format!("refs/heads/{}", branch), format!("refs/remotes/origin/{}", branch)],
+            "branch",
+            branch.clone(),
+        ),
+        GitRef::Tag(tag) => (vec![format!("refs/tags/{}", tag)], "tag", tag.clone()),
+        GitRef::Commit(rev) => (vec![rev.clone()], "revision", rev.clone()),
+        // Fetched by easycopy, or already present in a local clone
+        GitRef::PullRequest(number) => (
+            vec![
+                pull_request_ref(*number),
+                format!("refs/pull/{}/head", number),
+                format!("refs/merge-requests/{}/head", number),
+                format!("refs/remotes/origin/pr/{}", number),
+            ],
+            "pull request",
+            format!("#{}", number),
+        ),
+        GitRef::Default => (vec!["HEAD".to_string()], "revision", "HEAD".to_string()),
+    };
+
+    let object = specs
+        .iter()
+        .find_map(|spec| repo.revparse_single(spec).ok())
+        .with_context(|| format!("Failed to find {} '{}'", what, name))?;
+
+    object.peel_to_commit()
+        .with_context(|| format!("'{}' does not point to a commit", name))
+}
+
+/// Check whether a local path is a bare repository (e.g. a mirror)
+pub fn is_bare_repo(repo_path: &Path) -> bool {
+    Repository::open(repo_path)
+        .map(|repo| repo.is_bare())
+        .unwrap_or(false)
+}
+
 /// Get the HEAD commit hash of a repository
 pub fn get_head_commit(repo_path: &Path) -> Result<String> {
     let repo = Repository::open(repo_path)
@@ -79,13 +496,231 @@ pub fn get_head_commit(repo_path: &Path) -> Result<String> {
     Ok(commit.id().to_string())
 }
 
+/// Short name of the branch HEAD points to, or `None` when it is detached
+pub fn head_branch(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    if !head.is_branch() {
+        return None;
+    }
+    head.shorthand().map(str::to_string)
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    /// Create a repository with one commit per (path, content) pair
+    pub(crate) fn init_repo_with_commits(dir: &Path, commits: &[(&str, &str)]) -> Repository {
+        let repo = Repository::init(dir).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        for (path, content) in commits {
+            let file = dir.join(path);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(&file, content).unwrap();
+
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new(path)).unwrap();
+            index.write().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let parents: Vec<_> = repo
+                .head()
+                .ok()
+                .and_then(|h| h.peel_to_commit().ok())
+                .into_iter()
+                .collect();
+            let parent_refs: Vec<_> = parents.iter().collect();
+            repo.commit(Some("HEAD"), &sig, &sig, path, &tree, &parent_refs).unwrap();
+        }
+        repo
+    }
+
+    #[test]
+    fn test_only_exact_refs_fetch_shallow() {
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        assert!(fetches_shallow(&GitRef::Branch("main".to_string())));
+        assert!(fetches_shallow(&GitRef::Tag("v1".to_string())));
+        assert!(fetches_shallow(&GitRef::Commit(sha.to_string())));
+        assert!(!fetches_shallow(&GitRef::Commit(sha[..7].to_string())));
+        assert!(!fetches_shallow(&GitRef::Commit("main~3".to_string())));
+        assert!(!fetches_shallow(&GitRef::Default));
+    }
+
+    #[test]
+    fn test_resolve_commit_revspec() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = init_repo_with_commits(temp_dir.path(), &[("a.txt", "one"), ("a.txt", "two")]);
+
+        let head = resolve_commit(&repo, &GitRef::Default).unwrap();
+        let parent = resolve_commit(&repo, &GitRef::Commit("HEAD~1".to_string())).unwrap();
+        assert_eq!(head.parent_id(0).unwrap(), parent.id());
+        assert!(resolve_commit(&repo, &GitRef::Tag("missing".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_clone_bare_has_no_worktree() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        let repo = init_repo_with_commits(&source, &[("a.txt", "one"), ("b.txt", "two")]);
+        let head = repo.head().unwrap().peel_to_commit().unwrap().id();
+
+        let dest = temp_dir.path().join("bare");
+        clone_bare(source.to_str().unwrap(), &dest, &GitRef::Default, &CloneOptions::default())
+            .unwrap();
+
+        assert!(is_bare_repo(&dest));
+        assert!(!is_bare_repo(&source));
+        assert!(!dest.join("a.txt").exists());
+        let bare = open_repo(&dest).unwrap();
+        assert_eq!(resolve_commit(&bare, &GitRef::Default).unwrap().id(), head);
+    }
+
+    #[test]
+    fn test_clone_of_tag_and_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        let repo = init_repo_with_commits(&source, &[("a.txt", "one"), ("a.txt", "two")]);
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.tag_lightweight("v1", head.parent(0).unwrap().as_object(), false).unwrap();
+        let url = format!("file://{}", source.display());
+
+        // file:// clones are never shallow, but must still honour the ref
+        assert!(is_local_url(&url));
+        assert!(!is_local_url("git@github.com:owner/repo.git"));
+        assert!(!is_local_url("example.com:owner/repo.git"));
+        assert!(is_local_url("./dir:with/colon"));
+        assert!(is_local_url("C:\\repos\\project"));
+        let tag_dest = temp_dir.path().join("tag");
+        let shallow = CloneOptions { shallow: true, ..CloneOptions::default() };
+        clone_repo(&url, &tag_dest, &GitRef::Tag("v1".to_string()), &shallow).unwrap();
+        assert_eq!(std::fs::read_to_string(tag_dest.join("a.txt")).unwrap(), "one");
+
+        let commit_dest = temp_dir.path().join("commit");
+        let sha = head.id().to_string();
+        clone_repo(&url, &commit_dest, &GitRef::Commit(sha.clone()), &shallow).unwrap();
+        assert_eq!(get_head_commit(&commit_dest).unwrap(), sha);
+        assert_eq!(std::fs::read_to_string(commit_dest.join("a.txt")).unwrap(), "two");
+    }
+
+    /// Commit `content` to a.txt on top of HEAD without moving any branch,
+    /// and point `reference` at it, like a pull request head
+    fn commit_off_branch(repo: &Repository, reference: &str, content: &str) -> git2::Oid {
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        let parent = repo.head().unwrap().peel_to_commit().unwrap();
+        let blob = repo.blob(content.as_bytes()).unwrap();
+        let mut builder = repo.treebuilder(Some(&parent.tree().unwrap())).unwrap();
+        builder.insert("a.txt", blob, 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let oid = repo.commit(None, &sig, &sig, "pr", &tree, &[&parent]).unwrap();
+        repo.reference(reference, oid, true, "pr").unwrap();
+        oid
+    }
+
+    #[test]
+    fn test_clone_of_pull_request() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        let repo = init_repo_with_commits(&source, &[("a.txt", "main")]);
+        commit_off_branch(&repo, "refs/pull/7/head", "github pr");
+        let gitlab = commit_off_branch(&repo, "refs/merge-requests/3/head", "gitlab mr");
+        let url = source.to_str().unwrap();
+
+        let dest = temp_dir.path().join("pr");
+        clone_repo(url, &dest, &GitRef::PullRequest(7), &CloneOptions::default()).unwrap();
+        assert_eq!(std::fs::read_to_string(dest.join("a.txt")).unwrap(), "github pr");
+
+        let dest = temp_dir.path().join("mr");
+        clone_bare(url, &dest, &GitRef::PullRequest(3), &CloneOptions::default()).unwrap();
+        let bare = open_repo(&dest).unwrap();
+        assert_eq!(resolve_commit(&bare, &GitRef::PullRequest(3)).unwrap().id(), gitlab);
+
+        let dest = temp_dir.path().join("missing");
+        let err = clone_repo(url, &dest, &GitRef::PullRequest(99), &CloneOptions::default()).unwrap_err();
+        assert!(format!("{:#}", err).contains("Pull request #99 not found"));
+    }
+
+    /// A `git daemon` serving `base`, killed on drop
+    pub(crate) struct GitDaemon(std::process::Child);
+
+    impl Drop for GitDaemon {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    /// Start `git daemon` on a free port, or None where git is missing
+    pub(crate) fn git_daemon(base: &Path) -> Option<(GitDaemon, u16)> {
+        // Run git-daemon itself; `git daemon` would leave it orphaned when killed
+        let exec_path = Command::new("git").arg("--exec-path").output().ok()?;
+        let exec_path = String::from_utf8_lossy(&exec_path.stdout).trim().to_string();
+        let port = std::net::TcpListener::bind("127.0.0.1:0").ok()?.local_addr().ok()?.port();
+        let child = Command::new(Path::new(&exec_path).join("git-daemon"))
+            .arg("--export-all")
+            .arg("--listen=127.0.0.1")
+            .arg(format!("--port={}", port))
+            .arg(format!("--base-path={}", base.display()))
+            .arg(base)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .ok()?;
+        let daemon = GitDaemon(child);
+
+        for _ in 0..50 {
+            if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                return Some((daemon, port));
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        None
+    }
+
+    #[test]
+    fn test_clone_fetches_unadvertised_commit_by_id() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        let repo = init_repo_with_commits(&source, &[("a.txt", "main")]);
+        repo.config().unwrap().set_bool("uploadpack.allowAnySHA1InWant", true).unwrap();
+
+        // Left behind by a deleted branch, so only an explicit fetch finds it
+        let orphan = commit_off_branch(&repo, "refs/heads/deleted", "orphan");
+        repo.find_reference("refs/heads/deleted").unwrap().delete().unwrap();
+
+        let Some((_daemon, port)) = git_daemon(temp_dir.path()) else {
+            return;
+        };
+        let url = format!("git://127.0.0.1:{}/source", port);
+        let dest = temp_dir.path().join("sha");
+        clone_repo(&url, &dest, &GitRef::Commit(orphan.to_string()), &CloneOptions::default()).unwrap();
+        assert_eq!(get_head_commit(&dest).unwrap(), orphan.to_string());
+        assert_eq!(std::fs::read_to_string(dest.join("a.txt")).unwrap(), "orphan");
+    }
+
+    #[test]
+    fn test_sparse_clone_only_checks_out_subdir() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        init_repo_with_commits(
+            &source,
+            &[(".gitignore", "*.log"), ("docs/guide.md", "guide"), ("src/lib.rs", "lib")],
+        );
+
+        let dest = temp_dir.path().join("sparse");
+        let options = CloneOptions {
+            sparse_path: Some("src".to_string()),
+            ..CloneOptions::default()
+        };
+        clone_repo(source.to_str().unwrap(), &dest, &GitRef::Default, &options).unwrap();
+
+        assert!(dest.join("src/lib.rs").exists());
+        assert!(dest.join(".gitignore").exists());
+        assert!(!dest.join("docs").exists());
+    }
+
     #[test]
     #[ignore] // Requires network access
+    #[allow(unused_variables)]
     fn test_clone_repo() {
         let temp_dir = TempDir::new().unwrap();
         let dest = temp_dir.path().join("test_repo");
@@ -1,10 +1,14 @@
+use crate::batch::BatchEntry;
 use crate::cxml_gen;
-use crate::file_analyzer::{FileInfo, MARKDOWN_EXTENSIONS};
-use crate::tree_gen;
-use crate::utils::{bytes_human, html_escape, slugify};
+use crate::diff_view::{DiffLayout, DiffLine, FileDiff};
+use crate::file_analyzer::{FileCounts, FileInfo, MARKDOWN_EXTENSIONS};
+use crate::submodules::SubmoduleInfo;
+use crate::tokens::{self, TokenOptions, Tokenizer};
+use crate::tree_gen::{self, TreeEntry};
+use crate::utils::{bytes_human, html_escape, short_id, slugify};
 use anyhow::Result;
 use pulldown_cmark::{html, Parser};
-use std::fs;
+use std::collections::HashMap;
 use std::path::Path;
 use syntect::easy::HighlightLines;
 use syntect::highlighting::ThemeSet;
@@ -12,99 +16,141 @@ use syntect::html::{styled_line_to_highlighted_html, IncludeBackground};
 use syntect::parsing::SyntaxSet;
 use syntect::util::LinesWithEndings;
 
+/// One repository of a combined page (see `build_combined_html`)
+pub struct RepoSection<'a> {
+    pub repo_url: &'a str,
+    pub repo_name: &'a str,
+    pub head_commit: &'a str,
+    pub file_infos: &'a [FileInfo],
+    pub submodules: &'a [SubmoduleInfo],
+}
+
+/// The parts of a page rendered for one repository
+struct RenderedRepo {
+    tree_html: String,
+    toc_html: String,
+    sidebar_html: String,
+    sections_html: String,
+    skipped_html: String,
+    deleted_html: String,
+    counts: FileCounts,
+    token_count: usize,
+    estimated_tokens: usize,
+}
+
 /// Build the complete HTML output
 pub fn build_html(
     repo_url: &str,
-    repo_dir: &Path,
+    repo_name: &str,
     head_commit: &str,
     file_infos: &[FileInfo],
+    submodules: &[SubmoduleInfo],
+    tokens: &TokenOptions,
 ) -> Result<String> {
     // Load syntax highlighting assets
     let syntax_set = SyntaxSet::load_defaults_newlines();
     let theme_set = ThemeSet::load_defaults();
     let theme = &theme_set.themes["InspiredGitHub"];
 
-    // Generate CSS for syntax highlighting
-    let css = generate_css();
-
-    // Statistics
-    let rendered: Vec<_> = file_infos.iter().filter(|f| f.decision.include).collect();
-    let skipped_binary: Vec<_> = file_infos
-        .iter()
-        .filter(|f| f.decision.reason == "binary")
-        .collect();
-    let skipped_large: Vec<_> = file_infos
-        .iter()
-        .filter(|f| f.decision.reason == "too_large")
-        .collect();
-    let skipped_ignored: Vec<_> = file_infos
-        .iter()
-        .filter(|f| f.decision.reason == "ignored")
-        .collect();
-    let total_files = rendered.len() + skipped_binary.len() + skipped_large.len() + skipped_ignored.len();
-
-    // Generate directory tree
-    let tree_text = tree_gen::generate_tree(repo_dir)?;
+    let repo = RepoSection {
+        repo_url,
+        repo_name,
+        head_commit,
+        file_infos,
+        submodules,
+    };
+    let RenderedRepo {
+        tree_html,
+        toc_html,
+        sidebar_html,
+        sections_html,
+        skipped_html,
+        deleted_html,
+        counts,
+        token_count,
+        estimated_tokens,
+    } = render_repo(&repo, "file-", &syntax_set, theme, tokens.tokenizer)?;
 
     // Generate CXML for LLM view
-    let cxml_text = cxml_gen::generate_cxml(file_infos)?;
+    let cxml_text = cxml_gen::generate_cxml(file_infos, tokens)?;
 
-    // Build table of contents
-    let mut toc_items = Vec::new();
-    for info in &rendered {
-        let anchor = slugify(&info.rel);
-        toc_items.push(format!(
-            "<li><a href=\"#file-{}\">{}</a> <span class=\"muted\">({})</span></li>",
-            anchor,
-            html_escape(&info.rel),
-            bytes_human(info.size)
-        ));
-    }
-    let toc_html = toc_items.join("\n");
+    let meta_html = format!(
+        r##"<div><strong>Repository:</strong> <a href="{repo_url_escaped}">{repo_url_escaped}</a></div>
+        <small><strong>HEAD commit:</strong> {head_commit_escaped}</small>
+        <div class="counts">
+            {counts}
+        </div>
+        <div class="counts"><strong>Tokens:</strong> {tokens_summary}</div>"##,
+        repo_url_escaped = html_escape(repo_url),
+        head_commit_escaped = html_escape(head_commit),
+        counts = counts_html(&counts),
+        tokens_summary = tokens_summary(token_count, estimated_tokens, tokens.tokenizer),
+    );
+    let human_html = format!(
+        r##"<section>
+        <h2>Directory tree</h2>
+        <pre class="tree">{tree_html}</pre>
+      </section>
 
-    // Build file sections
-    let mut sections = Vec::new();
-    for info in &rendered {
-        let anchor = slugify(&info.rel);
-        let content = fs::read_to_string(&info.path)?;
+      <section class="toc-top">
+        <h2>Table of contents ({rendered_count})</h2>
+        <ul class="toc">{toc_html}</ul>
+      </section>
 
-        let body_html = if is_markdown(&info.rel) {
-            render_markdown(&content)
-        } else {
-            highlight_code(&content, &info.rel, &syntax_set, theme)?
-        };
+      <section>
+        <h2>Skipped items</h2>
+        {skipped_html}
+      </section>
+{deleted_html}
+      {sections_html}"##,
+        rendered_count = counts.rendered,
+    );
 
-        sections.push(format!(
-            r##"
-<section class="file-section" id="file-{anchor}">
-  <h2>{rel_escaped} <span class="muted">({size})</span></h2>
-  <div class="file-body">{body}</div>
-  <div class="back-top"><a href="#top">↑ Back to top</a></div>
-</section>
-"##,
-            anchor = anchor,
-            rel_escaped = html_escape(&info.rel),
-            size = bytes_human(info.size),
-            body = body_html
-        ));
-    }
-    let sections_html = sections.join("\n");
+    Ok(render_page(&Page {
+        title: format!("Flattened repo – {}", repo_url),
+        css: generate_css().to_string(),
+        sidebar_heading: format!("Contents ({})", counts.rendered),
+        folder_controls: true,
+        sidebar_html,
+        meta_html,
+        human_html,
+        cxml_text,
+    }))
+}
 
-    // Build skip lists
-    let skipped_html = format!(
-        "{}{}",
-        render_skip_list("Skipped binaries", &skipped_binary),
-        render_skip_list("Skipped large files", &skipped_large)
-    );
+/// Everything that differs between the single, combined and diff pages
+struct Page {
+    title: String,
+    css: String,
+    sidebar_heading: String,
+    /// Show the expand/collapse all buttons for the sidebar folder tree
+    folder_controls: bool,
+    sidebar_html: String,
+    meta_html: String,
+    human_html: String,
+    cxml_text: String,
+}
 
-    // Build final HTML
-    Ok(format!(
+/// Lay out a page: the sidebar, the meta section, the view toggle, and the
+/// human and LLM views
+fn render_page(page: &Page) -> String {
+    let folder_controls = if page.folder_controls {
+        r#"      <div class="tree-controls">
+        <button onclick="setFolders(true)">Expand all</button>
+        <button onclick="setFolders(false)">Collapse all</button>
+      </div>
+"#
+    } else {
+        ""
+    };
+
+    format!(
         r##"<!DOCTYPE html>
 <html lang="en">
 <head>
 <meta charset="utf-8" />
 <meta name="viewport" content="width=device-width, initial-scale=1" />
-<title>Flattened repo – {repo_url_escaped}</title>
+<title>{title}</title>
 <style>
 {css}
 </style>
@@ -114,10 +160,10 @@ pub fn build_html(
 
 <div class="page">
   <nav id="sidebar"><div class="sidebar-inner">
-      <h2>Contents ({rendered_count})</h2>
-      <ul class="toc toc-sidebar">
+      <h2>{sidebar_heading}</h2>
+{folder_controls}      <ul class="toc toc-sidebar">
         <li><a href="#top">↑ Back to top</a></li>
-        {toc_html}
+        {sidebar_html}
       </ul>
   </div></nav>
 
@@ -125,11 +171,7 @@ pub fn build_html(
 
     <section>
         <div class="meta">
-        <div><strong>Repository:</strong> <a href="{repo_url_escaped}">{repo_url_escaped}</a></div>
-        <small><strong>HEAD commit:</strong> {head_commit_escaped}</small>
-        <div class="counts">
-            <strong>Total files:</strong> {total_files} · <strong>Rendered:</strong> {rendered_count} · <strong>Skipped:</strong> {skipped_count}
-        </div>
+        {meta_html}
         </div>
     </section>
 
@@ -140,22 +182,7 @@ pub fn build_html(
     </div>
 
     <div id="human-view">
-      <section>
-        <h2>Directory tree</h2>
-        <pre>{tree_text_escaped}</pre>
-      </section>
-
-      <section class="toc-top">
-        <h2>Table of contents ({rendered_count})</h2>
-        <ul class="toc">{toc_html}</ul>
-      </section>
-
-      <section>
-        <h2>Skipped items</h2>
-        {skipped_html}
-      </section>
-
-      {sections_html}
+      {human_html}
     </div>
 
     <div id="llm-view">
@@ -177,23 +204,679 @@ pub fn build_html(
 </body>
 </html>
 "##,
-        repo_url_escaped = html_escape(repo_url),
-        head_commit_escaped = html_escape(head_commit),
-        total_files = total_files,
-        rendered_count = rendered.len(),
-        skipped_count = skipped_binary.len() + skipped_large.len() + skipped_ignored.len(),
-        toc_html = toc_html,
-        tree_text_escaped = html_escape(&tree_text),
-        skipped_html = skipped_html,
-        sections_html = sections_html,
-        cxml_text_escaped = html_escape(&cxml_text),
-        css = css,
+        title = html_escape(&page.title),
+        css = page.css,
+        sidebar_heading = page.sidebar_heading,
+        folder_controls = folder_controls,
+        sidebar_html = page.sidebar_html,
+        meta_html = page.meta_html,
+        human_html = page.human_html,
+        cxml_text_escaped = html_escape(&page.cxml_text),
         javascript = get_javascript(),
+    )
+}
+
+/// Build one page for several repositories: a top-level section per repo with
+/// its own tree, stats and skip lists. Anchors are namespaced by repo name and
+/// the LLM view holds one CXML with repo-prefixed sources.
+pub fn build_combined_html(repos: &[RepoSection], tokens: &TokenOptions) -> Result<String> {
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+    let theme_set = ThemeSet::load_defaults();
+    let theme = &theme_set.themes["InspiredGitHub"];
+
+    let mut toc_groups = Vec::new();
+    let mut sidebar_groups = Vec::new();
+    let mut repo_sections = Vec::new();
+    let mut counts = FileCounts::default();
+    let (mut token_count, mut estimated_tokens) = (0, 0);
+    for repo in repos {
+        let anchor = format!("repo-{}", slugify(repo.repo_name));
+        let parts = render_repo(repo, &format!("{}-file-", anchor), &syntax_set, theme, tokens.tokenizer)?;
+        counts.add(parts.counts);
+        token_count += parts.token_count;
+        estimated_tokens += parts.estimated_tokens;
+
+        toc_groups.push(format!(
+            "<li class=\"toc-repo\"><a href=\"#{anchor}\"><strong>{name}</strong></a> <span class=\"muted\">({count})</span>\n<ul class=\"toc\">\n{items}\n</ul></li>",
+            anchor = anchor,
+            name = html_escape(repo.repo_name),
+            count = parts.counts.rendered,
+            items = parts.toc_html
+        ));
+        let rendered_size: u64 = repo.file_infos.iter().filter(|f| f.decision.include).map(|f| f.size).sum();
+        sidebar_groups.push(format!(
+            "<li class=\"toc-repo\"><details open><summary><a href=\"#{anchor}\"><strong>{name}</strong></a> <span class=\"muted\">({count} · {size})</span></summary>\n<ul class=\"toc\">\n{items}\n</ul></details></li>",
+            anchor = anchor,
+            name = html_escape(repo.repo_name),
+            count = parts.counts.rendered,
+            size = bytes_human(rendered_size),
+            items = parts.sidebar_html
+        ));
+        repo_sections.push(format!(
+            r##"
+<section class="repo-section" id="{anchor}">
+  <h1>{name}</h1>
+  <div class="meta">
+    <div><strong>Repository:</strong> <a href="{url}">{url}</a></div>
+    <small><strong>HEAD commit:</strong> {head_commit}</small>
+    <div class="counts">
+      {counts}
+    </div>
+    <div class="counts"><strong>Tokens:</strong> {tokens_summary}</div>
+  </div>
+
+  <section>
+    <h2>Directory tree</h2>
+    <pre class="tree">{tree_html}</pre>
+  </section>
+
+  <section>
+    <h2>Skipped items</h2>
+    {skipped_html}
+  </section>
+{deleted_html}
+  {sections_html}
+</section>
+"##,
+            anchor = anchor,
+            name = html_escape(repo.repo_name),
+            url = html_escape(repo.repo_url),
+            head_commit = html_escape(repo.head_commit),
+            counts = counts_html(&parts.counts),
+            tokens_summary = tokens_summary(parts.token_count, parts.estimated_tokens, tokens.tokenizer),
+            tree_html = parts.tree_html,
+            skipped_html = parts.skipped_html,
+            deleted_html = parts.deleted_html,
+            sections_html = parts.sections_html
+        ));
+    }
+
+    let cxml_repos: Vec<_> = repos.iter().map(|r| (r.repo_name, r.file_infos)).collect();
+    let cxml_text = cxml_gen::generate_combined_cxml(&cxml_repos, tokens)?;
+    let repo_links: Vec<_> = repos
+        .iter()
+        .map(|r| format!("<a href=\"#repo-{}\">{}</a>", slugify(r.repo_name), html_escape(r.repo_name)))
+        .collect();
+    let title: Vec<_> = repos.iter().map(|r| r.repo_name).collect();
+
+    let meta_html = format!(
+        r##"<div><strong>Repositories ({repo_count}):</strong> {repo_links}</div>
+        <div class="counts">
+            {counts}
+        </div>
+        <div class="counts"><strong>Tokens:</strong> {tokens_summary}</div>"##,
+        repo_count = repos.len(),
+        repo_links = repo_links.join(" · "),
+        counts = counts_html(&counts),
+        tokens_summary = tokens_summary(token_count, estimated_tokens, tokens.tokenizer),
+    );
+    let human_html = format!(
+        r##"<section class="toc-top">
+        <h2>Table of contents ({rendered_count})</h2>
+        <ul class="toc">{toc_html}</ul>
+      </section>
+
+      {repo_sections}"##,
+        rendered_count = counts.rendered,
+        toc_html = toc_groups.join("\n"),
+        repo_sections = repo_sections.join("\n"),
+    );
+
+    Ok(render_page(&Page {
+        title: format!("Flattened repos – {}", title.join(", ")),
+        css: generate_css().to_string(),
+        sidebar_heading: format!("Contents ({})", counts.rendered),
+        folder_controls: true,
+        sidebar_html: sidebar_groups.join("\n"),
+        meta_html,
+        human_html,
+        cxml_text,
+    }))
+}
+
+/// Build the index page of a batch: a table linking each rendered page with
+/// its HEAD commit, file counts and size, followed by the sources that failed
+pub fn build_batch_index(entries: &[BatchEntry]) -> String {
+    let mut rows = Vec::new();
+    let mut failures = Vec::new();
+    for entry in entries {
+        match &entry.result {
+            Ok(page) => rows.push(format!(
+                "<tr><td><a href=\"{}\">{}</a></td><td><code>{}</code></td><td><code>{}</code></td><td class=\"num\">{} / {}</td><td class=\"num\">{}</td></tr>",
+                html_escape(&page.file_name),
+                html_escape(&page.name),
+                html_escape(&entry.source),
+                html_escape(&short_id(&page.head_commit)),
+                page.rendered_files,
+                page.total_files,
+                bytes_human(page.html_size)
+            )),
+            Err(e) => failures.push(format!(
+                "<li><code>{}</code> <span class='muted'>{}</span></li>",
+                html_escape(&entry.source),
+                html_escape(e)
+            )),
+        }
+    }
+
+    let failures_html = if failures.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "<section>\n  <h2>Failed ({})</h2>\n  <ul class='skip-list'>\n{}\n  </ul>\n</section>",
+            failures.len(),
+            failures.join("\n")
+        )
+    };
+
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8" />
+<meta name="viewport" content="width=device-width, initial-scale=1" />
+<title>Flattened repos – index</title>
+<style>
+{css}
+  .batch-index {{ border-collapse: collapse; }}
+  .batch-index th, .batch-index td {{ padding: 0.25rem 0.75rem; border-bottom: 1px solid #eee; text-align: left; }}
+  .batch-index td.num {{ text-align: right; white-space: nowrap; }}
+</style>
+</head>
+<body>
+<main class="container">
+  <section>
+    <h1>Repositories ({rendered} of {total} rendered)</h1>
+    <table class="batch-index">
+      <thead><tr><th>Repository</th><th>Source</th><th>HEAD</th><th>Files (rendered / total)</th><th>Page size</th></tr></thead>
+      <tbody>
+{rows}
+      </tbody>
+    </table>
+  </section>
+  {failures_html}
+</main>
+</body>
+</html>
+"##,
+        css = generate_css(),
+        rendered = rows.len(),
+        total = entries.len(),
+        rows = rows.join("\n"),
+        failures_html = failures_html,
+    )
+}
+
+/// Render the tree, TOC, file sections and skip lists of one repository.
+/// File anchors are `anchor_prefix` followed by the slugified path.
+fn render_repo(
+    repo: &RepoSection,
+    anchor_prefix: &str,
+    syntax_set: &SyntaxSet,
+    theme: &syntect::highlighting::Theme,
+    tokenizer: Tokenizer,
+) -> Result<RenderedRepo> {
+    let file_infos = repo.file_infos;
+    let submodules = repo.submodules;
+
+    let rendered: Vec<_> = file_infos.iter().filter(|f| f.decision.include).collect();
+    let link = |info: &FileInfo| format!("#{}{}", anchor_prefix, slugify(&info.rel));
+
+    // Build file sections, counting the tokens of each file
+    let mut sections = Vec::new();
+    let mut token_counts = HashMap::new();
+    let mut estimated_tokens = 0;
+    for info in &rendered {
+        let content = info.read_to_string()?;
+        let count = tokenizer.count(&content);
+        token_counts.insert(info.rel.as_str(), count);
+        estimated_tokens += tokens::estimate(&content);
+
+        let body_html = render_file_body(&info.rel, &content, syntax_set, theme)?;
+        sections.push(format!(
+            r##"
+<section class="file-section" id="{anchor_prefix}{anchor}">
+  <h2>{rel_escaped} <span class="muted">({size} · {tokens} tokens)</span></h2>
+  <div class="file-body">{body}</div>
+  <div class="back-top"><a href="#top">↑ Back to top</a></div>
+</section>
+"##,
+            anchor_prefix = anchor_prefix,
+            anchor = slugify(&info.rel),
+            rel_escaped = html_escape(&info.rel),
+            size = bytes_human(info.size),
+            tokens = tokens::tokens_human(count),
+            body = body_html
+        ));
+    }
+    let label = |info: &FileInfo| {
+        format!("{} · {} tokens", bytes_human(info.size), tokens::tokens_human(token_counts[info.rel.as_str()]))
+    };
+
+    // Generate directory tree
+    let tree_html = render_tree(repo, &link);
+
+    // Build table of contents, with submodule files grouped under a marker
+    let toc_item = |info: &FileInfo| {
+        format!(
+            "<li><a href=\"{}\">{}</a> <span class=\"muted\">({})</span></li>",
+            link(info),
+            html_escape(&info.rel),
+            label(info)
+        )
+    };
+    let owner = |info: &FileInfo| {
+        submodules
+            .iter()
+            .filter(|sm| info.rel.starts_with(&format!("{}/", sm.path)))
+            .max_by_key(|sm| sm.path.len())
+            .map(|sm| sm.path.as_str())
+    };
+
+    let mut toc_items: Vec<String> = rendered
+        .iter()
+        .filter(|info| owner(info).is_none())
+        .map(|info| toc_item(info))
+        .collect();
+    for sm in submodules {
+        let items: Vec<String> = rendered
+            .iter()
+            .filter(|info| owner(info) == Some(sm.path.as_str()))
+            .map(|info| toc_item(info))
+            .collect();
+        if items.is_empty() {
+            continue;
+        }
+        toc_items.push(format!(
+            "<li class=\"toc-submodule\"><div class=\"submodule-marker\"><strong>{}</strong> <span class=\"muted\">{}</span></div>\n<ul class=\"toc\">\n{}\n</ul></li>",
+            html_escape(&sm.path),
+            html_escape(&sm.label()),
+            items.join("\n")
+        ));
+    }
+
+    Ok(RenderedRepo {
+        tree_html,
+        toc_html: toc_items.join("\n"),
+        sidebar_html: render_sidebar_tree(repo, &link, &label),
+        sections_html: sections.join("\n"),
+        skipped_html: render_skipped(file_infos),
+        deleted_html: render_deleted(file_infos),
+        counts: FileCounts::of(file_infos),
+        token_count: token_counts.values().sum(),
+        estimated_tokens,
+    })
+}
+
+/// Describe a token total for the meta section, next to the bytes/4 estimate
+fn tokens_summary(count: usize, estimated: usize, tokenizer: Tokenizer) -> String {
+    let estimate = format!("~{} (bytes/4 estimate)", tokens::tokens_human(estimated));
+    match tokenizer {
+        Tokenizer::Estimate => estimate,
+        _ => format!("{} ({}) · {}", tokens::tokens_human(count), tokenizer.name(), estimate),
+    }
+}
+
+/// The "Total files · Rendered · Skipped" line of a meta section, with the
+/// deleted files when there are any
+pub fn counts_html(counts: &FileCounts) -> String {
+    let deleted = if counts.deleted > 0 {
+        format!(" · <strong>Deleted:</strong> {}", counts.deleted)
+    } else {
+        String::new()
+    };
+    format!(
+        "<strong>Total files:</strong> {} · <strong>Rendered:</strong> {} · <strong>Skipped:</strong> {}{}",
+        counts.total, counts.rendered, counts.skipped, deleted
+    )
+}
+
+/// Render the skip lists of a repository, one per reason
+pub fn render_skipped(file_infos: &[FileInfo]) -> String {
+    let with_reason = |reason: &str| -> Vec<&FileInfo> {
+        file_infos.iter().filter(|f| f.decision.reason == reason).collect()
+    };
+    format!(
+        "{}{}{}{}{}{}",
+        render_skip_list("Skipped binaries", &with_reason("binary")),
+        render_skip_list("Skipped large files", &with_reason("too_large")),
+        render_skip_list("Not fetched (partial clone)", &with_reason("not_fetched")),
+        render_skip_list("Ignored by .gitignore", &with_reason("gitignored")),
+        render_skip_list("Excluded by pattern", &with_reason("excluded_by_pattern")),
+        render_skip_list("Symlinks", &with_reason("symlink"))
+    )
+}
+
+/// Render the section listing files deleted in `--changes`, or nothing when
+/// there are none
+pub fn render_deleted(file_infos: &[FileInfo]) -> String {
+    let deleted: Vec<&FileInfo> = file_infos.iter().filter(|f| f.decision.is_deleted()).collect();
+    if deleted.is_empty() {
+        return String::new();
+    }
+    format!(
+        "\n      <section>\n        <h2>Deleted files</h2>\n        {}\n      </section>\n",
+        render_skip_list("Deleted in these changes", &deleted)
+    )
+}
+
+/// Render the body of a file section: Markdown as HTML, anything else as
+/// highlighted code
+pub fn render_file_body(
+    rel: &str,
+    content: &str,
+    syntax_set: &SyntaxSet,
+    theme: &syntect::highlighting::Theme,
+) -> Result<String> {
+    if is_markdown(rel) {
+        Ok(render_markdown(content))
+    } else {
+        highlight_code(content, rel, syntax_set, theme)
+    }
+}
+
+/// Build an HTML page of per-file diffs between two revisions, with a
+/// summary table and the structured diff in the LLM view
+pub fn build_diff_html(
+    repo_url: &str,
+    base: (&str, &str), // (revision as given, commit id)
+    head: (&str, &str),
+    files: &[FileDiff],
+    layout: DiffLayout,
+) -> Result<String> {
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+    let theme_set = ThemeSet::load_defaults();
+    let theme = &theme_set.themes["InspiredGitHub"];
+
+    let additions: usize = files.iter().map(|f| f.additions).sum();
+    let deletions: usize = files.iter().map(|f| f.deletions).sum();
+
+    // Summary table and TOC
+    let mut toc_items = Vec::new();
+    let mut summary_rows = Vec::new();
+    for file in files {
+        let anchor = format!("diff-{}", slugify(file.path()));
+        toc_items.push(format!(
+            "<li><a href=\"#{}\">{}</a> {}</li>",
+            anchor,
+            html_escape(&file.display_path()),
+            line_counts(file)
+        ));
+        summary_rows.push(format!(
+            "<tr><td><a href=\"#{}\">{}</a></td><td>{}</td><td class=\"num add\">+{}</td><td class=\"num del\">−{}</td></tr>",
+            anchor,
+            html_escape(&file.display_path()),
+            html_escape(&file.status),
+            file.additions,
+            file.deletions
+        ));
+    }
+
+    // Per-file sections
+    let mut sections = Vec::new();
+    for file in files {
+        let body = match &file.note {
+            Some(note) => format!("<p class=\"muted\">No diff shown ({})</p>", html_escape(note)),
+            None if file.hunks.is_empty() => "<p class=\"muted\">No content changes</p>".to_string(),
+            None => render_file_diff(file, layout, &syntax_set, theme)?,
+        };
+        sections.push(format!(
+            r##"
+<section class="file-section" id="diff-{anchor}">
+  <h2>{path} <span class="muted">({status})</span> {counts}</h2>
+  <div class="file-body">{body}</div>
+  <div class="back-top"><a href="#top">↑ Back to top</a></div>
+</section>
+"##,
+            anchor = slugify(file.path()),
+            path = html_escape(&file.display_path()),
+            status = html_escape(&file.status),
+            counts = line_counts(file),
+            body = body
+        ));
+    }
+
+    let cxml_text = cxml_gen::generate_diff_cxml(base.1, head.1, files);
+
+    let meta_html = format!(
+        r##"<div><strong>Repository:</strong> <a href="{repo_url_escaped}">{repo_url_escaped}</a></div>
+        <div><strong>Diff:</strong> <code>{base_rev}</code> ({base_short}) → <code>{head_rev}</code> ({head_short})</div>
+        <div class="counts">
+            <strong>Files changed:</strong> {file_count} · <span class="add">+{additions}</span> · <span class="del">−{deletions}</span>
+        </div>"##,
+        repo_url_escaped = html_escape(repo_url),
+        base_rev = html_escape(base.0),
+        head_rev = html_escape(head.0),
+        base_short = short_id(base.1),
+        head_short = short_id(head.1),
+        file_count = files.len(),
+        additions = additions,
+        deletions = deletions,
+    );
+    let human_html = format!(
+        r##"<section>
+        <h2>Summary</h2>
+        <table class="diff-summary">
+          <thead><tr><th>File</th><th>Status</th><th>Added</th><th>Removed</th></tr></thead>
+          <tbody>
+{summary_rows}
+          </tbody>
+        </table>
+      </section>
+
+      {sections_html}"##,
+        summary_rows = summary_rows.join("\n"),
+        sections_html = sections.join("\n"),
+    );
+
+    Ok(render_page(&Page {
+        title: format!("Diff {}..{} – {}", base.0, head.0, repo_url),
+        css: format!("{}\n{}", generate_css(), generate_diff_css()),
+        sidebar_heading: format!("Files changed ({})", files.len()),
+        folder_controls: false,
+        sidebar_html: toc_items.join("\n"),
+        meta_html,
+        human_html,
+        cxml_text,
+    }))
+}
+
+/// `+N −M` badge for a file
+fn line_counts(file: &FileDiff) -> String {
+    format!(
+        "<span class=\"muted\"><span class=\"add\">+{}</span> <span class=\"del\">−{}</span></span>",
+        file.additions, file.deletions
+    )
+}
+
+/// Render the hunks of a file as a diff table. Lines are highlighted like
+/// `highlight_code`, with the old and new side each keeping their own
+/// parser state so multi-line constructs stay correct.
+fn render_file_diff(
+    file: &FileDiff,
+    layout: DiffLayout,
+    syntax_set: &SyntaxSet,
+    theme: &syntect::highlighting::Theme,
+) -> Result<String> {
+    let syntax = syntax_set
+        .find_syntax_by_extension(
+            Path::new(file.path())
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("txt"),
+        )
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+    let mut old_side = HighlightLines::new(syntax, theme);
+    let mut new_side = HighlightLines::new(syntax, theme);
+
+    let highlight = |highlighter: &mut HighlightLines, line: &DiffLine| -> Result<String> {
+        let ranges = highlighter.highlight_line(&line.content, syntax_set)?;
+        let html = styled_line_to_highlighted_html(&ranges[..], IncludeBackground::No)?;
+        Ok(html.replace('\n', ""))
+    };
+    let lineno = |n: Option<u32>| n.map(|n| n.to_string()).unwrap_or_default();
+
+    let mut rows = Vec::new();
+    for hunk in &file.hunks {
+        // Both layouts have four columns
+        rows.push(format!(
+            "<tr class=\"hunk-header\"><td colspan=\"4\">{}</td></tr>",
+            html_escape(&hunk.header)
+        ));
+
+        match layout {
+            DiffLayout::Unified => {
+                for line in &hunk.lines {
+                    let (class, code) = match line.origin {
+                        '+' => ("line-add", highlight(&mut new_side, line)?),
+                        '-' => ("line-del", highlight(&mut old_side, line)?),
+                        _ => {
+                            highlight(&mut old_side, line)?;
+                            ("line-ctx", highlight(&mut new_side, line)?)
+                        }
+                    };
+                    rows.push(format!(
+                        "<tr class=\"{}\"><td class=\"ln\">{}</td><td class=\"ln\">{}</td><td class=\"sign\">{}</td><td class=\"code\">{}</td></tr>",
+                        class,
+                        lineno(line.old_lineno),
+                        lineno(line.new_lineno),
+                        line.origin,
+                        code
+                    ));
+                }
+            }
+            DiffLayout::Split => {
+                // Pair each run of removed lines with the added lines after it
+                let mut i = 0;
+                while i < hunk.lines.len() {
+                    let line = &hunk.lines[i];
+                    if line.origin == ' ' {
+                        let old_code = highlight(&mut old_side, line)?;
+                        let new_code = highlight(&mut new_side, line)?;
+                        rows.push(format!(
+                            "<tr class=\"line-ctx\"><td class=\"ln\">{}</td><td class=\"code\">{}</td><td class=\"ln\">{}</td><td class=\"code\">{}</td></tr>",
+                            lineno(line.old_lineno), old_code, lineno(line.new_lineno), new_code
+                        ));
+                        i += 1;
+                        continue;
+                    }
+
+                    let removed: Vec<_> = hunk.lines[i..].iter().take_while(|l| l.origin == '-').collect();
+                    let added: Vec<_> = hunk.lines[i + removed.len()..].iter().take_while(|l| l.origin == '+').collect();
+                    for row in 0..removed.len().max(added.len()) {
+                        let (old_ln, old_code, old_class) = match removed.get(row) {
+                            Some(l) => (lineno(l.old_lineno), highlight(&mut old_side, l)?, "line-del"),
+                            None => (String::new(), String::new(), "empty"),
+                        };
+                        let (new_ln, new_code, new_class) = match added.get(row) {
+                            Some(l) => (lineno(l.new_lineno), highlight(&mut new_side, l)?, "line-add"),
+                            None => (String::new(), String::new(), "empty"),
+                        };
+                        rows.push(format!(
+                            "<tr><td class=\"ln {oc}\">{}</td><td class=\"code {oc}\">{}</td><td class=\"ln {nc}\">{}</td><td class=\"code {nc}\">{}</td></tr>",
+                            old_ln, old_code, new_ln, new_code, oc = old_class, nc = new_class
+                        ));
+                    }
+                    i += removed.len() + added.len();
+                }
+            }
+        }
+    }
+
+    let class = match layout {
+        DiffLayout::Unified => "diff diff-unified",
+        DiffLayout::Split => "diff diff-split",
+    };
+    Ok(format!(
+        "<div class=\"highlight\"><table class=\"{}\">\n{}\n</table></div>",
+        class,
+        rows.join("\n")
     ))
 }
 
+/// Render the annotated directory tree, with rendered files linking to
+/// `link(file)`
+pub fn render_tree(repo: &RepoSection, link: &dyn Fn(&FileInfo) -> String) -> String {
+    tree_gen::tree_lines(repo.repo_name, repo.file_infos, repo.submodules)
+        .iter()
+        .map(|line| {
+            let name = match &line.entry {
+                TreeEntry::File(info) if info.decision.include => format!(
+                    "<a href=\"{}\">{}</a>",
+                    link(info),
+                    html_escape(&line.name)
+                ),
+                _ => html_escape(&line.name),
+            };
+            let annotation = line.annotation();
+            if annotation.is_empty() {
+                format!("{}{}", html_escape(&line.prefix), name)
+            } else {
+                format!(
+                    "{}{}  <span class=\"muted\">{}</span>",
+                    html_escape(&line.prefix),
+                    name,
+                    html_escape(&annotation)
+                )
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render the rendered files as a nested, collapsible folder tree for the
+/// sidebar. Folders show how many files they hold and their total size;
+/// submodule folders also show their URL and pinned commit. Files link to
+/// `link(file)` and are annotated with `label(file)`.
+pub fn render_sidebar_tree(
+    repo: &RepoSection,
+    link: &dyn Fn(&FileInfo) -> String,
+    label: &dyn Fn(&FileInfo) -> String,
+) -> String {
+    fn walk(
+        node: &tree_gen::DirNode,
+        rel: &str,
+        repo: &RepoSection,
+        link: &dyn Fn(&FileInfo) -> String,
+        label: &dyn Fn(&FileInfo) -> String,
+        items: &mut Vec<String>,
+    ) {
+        for (name, child) in &node.dirs {
+            let child_rel = format!("{}{}", rel, name);
+            let marker = match repo.submodules.iter().find(|sm| sm.path == child_rel) {
+                Some(sm) => format!(" <span class=\"submodule-marker muted\">{}</span>", html_escape(&sm.label())),
+                None => String::new(),
+            };
+            let mut children = Vec::new();
+            walk(child, &format!("{}/", child_rel), repo, link, label, &mut children);
+            items.push(format!(
+                "<li class=\"toc-dir\"><details><summary>{}/ <span class=\"muted\">({} · {})</span>{}</summary>\n<ul class=\"toc\">\n{}\n</ul></details></li>",
+                html_escape(name),
+                child.file_count(),
+                bytes_human(child.total_size()),
+                marker,
+                children.join("\n")
+            ));
+        }
+        for (name, info) in &node.files {
+            items.push(format!(
+                "<li><a href=\"{}\" title=\"{}\">{}</a> <span class=\"muted\">({})</span></li>",
+                link(info),
+                html_escape(&info.rel),
+                html_escape(name),
+                label(info)
+            ));
+        }
+    }
+
+    let root = tree_gen::DirNode::build(repo.file_infos.iter().filter(|f| f.decision.include));
+    let mut items = Vec::new();
+    walk(&root, "", repo, link, label, &mut items);
+    items.join("\n")
+}
+
 /// Check if a file is markdown based on extension
-fn is_markdown(filename: &str) -> bool {
+pub(crate) fn is_markdown(filename: &str) -> bool {
     MARKDOWN_EXTENSIONS
         .iter()
         .any(|ext| filename.to_lowercase().ends_with(ext))
@@ -244,10 +927,15 @@ fn render_skip_list(title: &str, items: &[&FileInfo]) -> String {
 
     let mut lis = Vec::new();
     for info in items {
+        // Symlinks show where they point instead of a size
+        let detail = match &info.symlink_target {
+            Some(target) => format!("→ {}", target),
+            None => info.size_human(),
+        };
         lis.push(format!(
             "<li><code>{}</code> <span class='muted'>({})</span></li>",
             html_escape(&info.rel),
-            bytes_human(info.size)
+            html_escape(&detail)
         ));
     }
 
@@ -260,7 +948,7 @@ fn render_skip_list(title: &str, items: &[&FileInfo]) -> String {
 }
 
 /// Generate CSS styles
-fn generate_css() -> &'static str {
+pub fn generate_css() -> &'static str {
     r#"
   body {
     font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, Helvetica, Arial, 'Apple Color Emoji','Segoe UI Emoji';
@@ -285,10 +973,27 @@ fn generate_css() -> &'static str {
   .toc li { padding: 0.15rem 0; white-space: nowrap; }
   .toc a { text-decoration: none; color: #0366d6; display: inline-block; text-decoration: none; }
   .toc a:hover { text-decoration: underline; }
+  .toc-submodule { margin-top: 0.5rem; }
+  .toc-repo { margin-top: 0.5rem; }
+  .toc-repo > .toc { padding-left: 1rem; border-left: 2px solid #d1d9e0; }
+  .repo-section { border-top: 3px solid #d1d9e0; margin-top: 1.5rem; padding-top: 0.5rem; }
+  .repo-section > h1 { margin: 0.5rem 0; font-size: 1.5rem; }
+  .toc-submodule > .toc { padding-left: 1rem; border-left: 2px solid #d1d9e0; }
+  .submodule-marker { white-space: normal; }
+  .toc details > summary { cursor: pointer; white-space: nowrap; }
+  .toc details > .toc { padding-left: 1rem; margin-left: 0.3rem; border-left: 1px solid #e1e4e8; }
+  .toc a.current { font-weight: 600; }
+  .tree-controls { display: flex; gap: 0.25rem; margin-bottom: 0.5rem; }
+  .tree-controls button {
+    font-size: 0.8rem; padding: 0.15rem 0.5rem; cursor: pointer;
+    border: 1px solid #d1d9e0; background: white; border-radius: 4px;
+  }
 
   main.container { padding-top: 1rem; }
 
   pre { background: #f6f8fa; padding: 0.75rem; overflow: auto; border-radius: 6px; }
+  pre.tree a { text-decoration: none; color: #0366d6; }
+  pre.tree a:hover { text-decoration: underline; }
   code { font-family: ui-monospace, SFMono-Regular, Menlo, Monaco, Consolas, 'Liberation Mono','Courier New', monospace; }
   .highlight { overflow-x: auto; }
   .file-section { padding: 1rem; border-top: 1px solid #eee; }
@@ -354,8 +1059,34 @@ fn generate_css() -> &'static str {
 "#
 }
 
+/// Extra CSS for the diff page
+fn generate_diff_css() -> &'static str {
+    r#"
+  .add { color: #1a7f37; }
+  .del { color: #cf222e; }
+  .diff-summary { border-collapse: collapse; margin-bottom: 1rem; }
+  .diff-summary th, .diff-summary td { padding: 0.2rem 0.75rem; border-bottom: 1px solid #eee; text-align: left; }
+  .diff-summary td.num { text-align: right; font-family: ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; }
+
+  table.diff {
+    width: 100%; border-collapse: collapse; table-layout: fixed;
+    font-family: ui-monospace, SFMono-Regular, Menlo, Monaco, Consolas, 'Liberation Mono','Courier New', monospace;
+    font-size: 0.85em; background: #fff; border: 1px solid #d1d9e0; border-radius: 6px;
+  }
+  table.diff td { padding: 0 0.5rem; vertical-align: top; }
+  table.diff td.code { white-space: pre-wrap; word-break: break-all; }
+  table.diff td.ln { width: 3.5em; color: #8c959f; text-align: right; user-select: none; }
+  table.diff td.sign { width: 1em; user-select: none; }
+  table.diff-split td.code { width: calc(50% - 3.5em); }
+  table.diff tr.hunk-header td { background: #ddf4ff; color: #57606a; padding: 0.2rem 0.5rem; }
+  table.diff .line-add, table.diff tr.line-add td { background: #e6ffec; }
+  table.diff .line-del, table.diff tr.line-del td { background: #ffebe9; }
+  table.diff .empty { background: #f6f8fa; }
+"#
+}
+
 /// Get JavaScript code for view toggling
-fn get_javascript() -> &'static str {
+pub fn get_javascript() -> &'static str {
     r#"
 function showHumanView() {
   document.getElementById('human-view').style.display = 'block';
@@ -377,5 +1108,39 @@ function showLLMView() {
     textArea.select();
   }, 100);
 }
+
+function setFolders(open) {
+  document.querySelectorAll('#sidebar details').forEach(d => d.open = open);
+}
+
+// Highlight the sidebar entry of the file in view and expand its folders
+(function () {
+  const sidebar = document.getElementById('sidebar');
+  if (!sidebar || !('IntersectionObserver' in window)) return;
+  const links = new Map();
+  sidebar.querySelectorAll("a[href^='#']").forEach(a => links.set(a.getAttribute('href').slice(1), a));
+  let current = null;
+
+  const observer = new IntersectionObserver(entries => {
+    entries.forEach(entry => {
+      const link = links.get(entry.target.id);
+      if (!entry.isIntersecting || !link || link === current) return;
+      if (current) current.classList.remove('current');
+      current = link;
+      link.classList.add('current');
+      for (let el = link.parentElement; el && el !== sidebar; el = el.parentElement) {
+        if (el.tagName === 'DETAILS') el.open = true;
+      }
+      // Keep the entry visible without scrolling the page itself
+      const r = link.getBoundingClientRect();
+      const s = sidebar.getBoundingClientRect();
+      if (r.top < s.top || r.bottom > s.bottom) {
+        sidebar.scrollTop += r.top - s.top - s.height / 2;
+      }
+    });
+  }, { rootMargin: '0px 0px -70% 0px' });
+
+  document.querySelectorAll('.file-section').forEach(section => observer.observe(section));
+})();
 "#
 }
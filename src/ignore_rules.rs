@@ -0,0 +1,309 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Per-directory ignore files, in increasing order of precedence
+const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore"];
+
+/// Stack of gitignore matchers mirroring git's precedence rules.
+///
+/// The global excludes file has the lowest precedence, followed by the
+/// repository's `info/exclude`, then every `.gitignore`/`.ignore` from the
+/// top of the working tree down to the directory currently being walked.
+/// The scanned directory may be anywhere inside a checkout or a linked
+/// worktree; the files above it are read up front.
+///
+/// Like git, the rules only apply to untracked paths: files in the index and
+/// the directories holding them are never ignored.
+pub struct IgnoreRules {
+    matchers: Vec<Gitignore>,
+    enabled: bool,
+    /// The scanned directory, as given
+    repo_root: PathBuf,
+    /// Its canonical path, which matchers and the working tree are based on
+    abs_root: PathBuf,
+    /// Canonical top of the working tree holding `repo_root`, if any
+    workdir: Option<PathBuf>,
+    /// Working-tree-relative paths of tracked files
+    tracked: HashSet<String>,
+    /// Working-tree-relative directories that contain tracked files
+    tracked_dirs: HashSet<String>,
+}
+
+impl IgnoreRules {
+    /// Load the global and repository-wide rules for `repo_root`, and those
+    /// of the directories above it up to the top of its working tree
+    pub fn new(repo_root: &Path, enabled: bool) -> Self {
+        let abs_root = repo_root.canonicalize().unwrap_or_else(|_| repo_root.to_path_buf());
+        let repo = if enabled { git2::Repository::discover(&abs_root).ok() } else { None };
+        let workdir = repo
+            .as_ref()
+            .and_then(|repo| repo.workdir())
+            .and_then(|dir| dir.canonicalize().ok())
+            .filter(|dir| abs_root.starts_with(dir));
+
+        let mut matchers = Vec::new();
+        if enabled {
+            let (global, _) = Gitignore::global();
+            if !global.is_empty() {
+                matchers.push(global);
+            }
+        }
+
+        // Shared by all worktrees of the repository
+        if let (Some(repo), Some(workdir)) = (&repo, &workdir) {
+            let exclude = common_dir(repo).join("info").join("exclude");
+            if exclude.is_file() {
+                let mut builder = GitignoreBuilder::new(workdir);
+                builder.add(&exclude);
+                if let Ok(matcher) = builder.build() {
+                    matchers.push(matcher);
+                }
+            }
+        }
+
+        let tracked = match &repo {
+            Some(repo) if workdir.is_some() => tracked_paths(repo),
+            _ => HashSet::new(),
+        };
+        let mut tracked_dirs = HashSet::new();
+        for path in &tracked {
+            let mut dir = path.as_str();
+            while let Some((parent, _)) = dir.rsplit_once('/') {
+                if !tracked_dirs.insert(parent.to_string()) {
+                    break;
+                }
+                dir = parent;
+            }
+        }
+
+        let mut rules = Self {
+            matchers,
+            enabled,
+            repo_root: repo_root.to_path_buf(),
+            abs_root,
+            workdir,
+            tracked,
+            tracked_dirs,
+        };
+
+        // Ignore files above the scanned directory, outermost first
+        if let Some(workdir) = rules.workdir.clone() {
+            let mut above: Vec<PathBuf> = rules
+                .abs_root
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| dir.starts_with(&workdir))
+                .map(Path::to_path_buf)
+                .collect();
+            above.reverse();
+            for dir in above {
+                rules.push_abs_dir(&dir);
+            }
+        }
+        rules
+    }
+
+    /// Push the ignore files found in `dir`. Returns true if a matcher was
+    /// added, in which case the caller must call `pop` when leaving `dir`.
+    pub fn push_dir(&mut self, dir: &Path) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let dir = self.absolute(dir);
+        self.push_abs_dir(&dir)
+    }
+
+    fn push_abs_dir(&mut self, dir: &Path) -> bool {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+        for name in IGNORE_FILES {
+            let file = dir.join(name);
+            if file.is_file() {
+                builder.add(&file);
+                found = true;
+            }
+        }
+        if !found {
+            return false;
+        }
+
+        match builder.build() {
+            Ok(matcher) if !matcher.is_empty() => {
+                self.matchers.push(matcher);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remove the matcher added by the last successful `push_dir`
+    pub fn pop(&mut self) {
+        self.matchers.pop();
+    }
+
+    /// Check whether a path is ignored. The innermost matching rule wins, so
+    /// a negated pattern in a nested `.gitignore` can re-include a file.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if self.is_tracked(path, is_dir) {
+            return false;
+        }
+        if self.matches(path, is_dir) {
+            return true;
+        }
+        // An ignored directory holding tracked files is still walked, but the
+        // untracked files in it stay ignored
+        path.ancestors()
+            .skip(1)
+            .take_while(|dir| *dir != self.repo_root)
+            .any(|dir| self.is_tracked(dir, true) && self.matches(dir, true))
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        let path = self.absolute(path);
+        for matcher in self.matchers.iter().rev() {
+            match matcher.matched(&path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => {}
+            }
+        }
+        false
+    }
+
+    /// `path` (under the scanned directory as given) based on its canonical path
+    fn absolute(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(&self.repo_root) {
+            Ok(rel) => self.abs_root.join(rel),
+            Err(_) => path.to_path_buf(),
+        }
+    }
+
+    fn is_tracked(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(workdir) = &self.workdir else {
+            return false;
+        };
+        let path = self.absolute(path);
+        let Ok(rel) = path.strip_prefix(workdir) else {
+            return false;
+        };
+        let rel = rel.to_string_lossy().replace('\\', "/");
+        if is_dir {
+            self.tracked_dirs.contains(&rel)
+        } else {
+            self.tracked.contains(&rel)
+        }
+    }
+}
+
+/// The git directory shared by all worktrees; a linked worktree's own git
+/// directory names it in its `commondir` file
+fn common_dir(repo: &git2::Repository) -> PathBuf {
+    match std::fs::read_to_string(repo.path().join("commondir")) {
+        Ok(dir) => repo.path().join(dir.trim()),
+        Err(_) => repo.path().to_path_buf(),
+    }
+}
+
+/// Paths in the index of `repo`
+fn tracked_paths(repo: &git2::Repository) -> HashSet<String> {
+    let Ok(index) = repo.index() else {
+        return HashSet::new();
+    };
+    index
+        .iter()
+        .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_nested_gitignore_precedence() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\ntarget/\n").unwrap();
+        fs::write(root.join("sub/.gitignore"), "!keep.log\n").unwrap();
+
+        let mut rules = IgnoreRules::new(root, true);
+        assert!(rules.push_dir(root));
+        assert!(rules.is_ignored(&root.join("target"), true));
+        assert!(rules.is_ignored(&root.join("debug.log"), false));
+        assert!(!rules.is_ignored(&root.join("main.rs"), false));
+
+        assert!(rules.push_dir(&root.join("sub")));
+        assert!(!rules.is_ignored(&root.join("sub/keep.log"), false));
+        assert!(rules.is_ignored(&root.join("sub/other.log"), false));
+        rules.pop();
+
+        let mut disabled = IgnoreRules::new(root, false);
+        assert!(!disabled.push_dir(root));
+        assert!(!disabled.is_ignored(&root.join("debug.log"), false));
+    }
+
+    #[test]
+    fn test_tracked_files_are_never_ignored() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("build")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\nbuild/\n").unwrap();
+        fs::write(root.join("kept.log"), "tracked anyway").unwrap();
+        fs::write(root.join("build/config.txt"), "tracked anyway").unwrap();
+        fs::write(root.join("other.log"), "untracked").unwrap();
+
+        let repo = git2::Repository::init(root).unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new("kept.log")).unwrap();
+        index.add_path(Path::new("build/config.txt")).unwrap();
+        index.write().unwrap();
+
+        let mut rules = IgnoreRules::new(root, true);
+        assert!(rules.push_dir(root));
+        assert!(!rules.is_ignored(&root.join("kept.log"), false));
+        assert!(!rules.is_ignored(&root.join("build"), true));
+        assert!(!rules.is_ignored(&root.join("build/config.txt"), false));
+        assert!(rules.is_ignored(&root.join("build/cache.bin"), false));
+        assert!(rules.is_ignored(&root.join("other.log"), false));
+    }
+
+    /// Scanning a subdirectory of a checkout, or of a linked worktree, still
+    /// applies the ignore files above it and the repository's exclude file
+    #[test]
+    fn test_subdirectory_uses_rules_from_the_working_tree_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("repo");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(root.join("sub/kept.log"), "tracked anyway").unwrap();
+
+        let repo = git2::Repository::init(&root).unwrap();
+        fs::write(repo.path().join("info/exclude"), "secret.txt\n").unwrap();
+        let mut index = repo.index().unwrap();
+        index.add_path(Path::new(".gitignore")).unwrap();
+        index.add_path(Path::new("sub/kept.log")).unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[]).unwrap();
+
+        let worktree = temp_dir.path().join("linked");
+        repo.worktree("linked", &worktree, None).unwrap();
+
+        for checkout in [&root, &worktree] {
+            let sub = checkout.join("sub");
+            let mut rules = IgnoreRules::new(&sub, true);
+            rules.push_dir(&sub);
+            assert!(rules.is_ignored(&sub.join("debug.log"), false));
+            assert!(rules.is_ignored(&sub.join("secret.txt"), false));
+            assert!(!rules.is_ignored(&sub.join("kept.log"), false));
+            assert!(!rules.is_ignored(&sub.join("main.rs"), false));
+        }
+    }
+}
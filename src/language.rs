@@ -0,0 +1,61 @@
+use std::path::Path;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+
+/// Fence tags for syntaxes whose names don't lowercase to a common tag
+const FENCE_TAGS: &[(&str, &str)] = &[
+    ("Bourne Again Shell (bash)", "bash"),
+    ("C++", "cpp"),
+    ("C#", "csharp"),
+    ("Objective-C", "objc"),
+    ("Objective-C++", "objcpp"),
+    ("JavaScript (Babel)", "javascript"),
+    ("Regular Expression", "regex"),
+    ("Graphviz (DOT)", "dot"),
+    ("Emacs Lisp", "elisp"),
+];
+
+/// Detect the language of a file the way it is highlighted: by extension
+/// (or file name, e.g. `Makefile`), then by its first line (shebangs).
+/// Returns a short tag usable in Markdown code fences, e.g. `rust` or
+/// `bash`; unknown files fall back to their lowercased extension.
+pub fn detect(syntax_set: &SyntaxSet, rel: &str, content: &str) -> Option<String> {
+    let path = Path::new(rel);
+    let extension = path.extension().and_then(|e| e.to_str());
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+
+    let syntax = extension
+        .and_then(|ext| syntax_set.find_syntax_by_extension(ext))
+        .or_else(|| syntax_set.find_syntax_by_extension(file_name))
+        .or_else(|| syntax_set.find_syntax_by_first_line(content));
+
+    match syntax {
+        Some(syntax) if syntax.name != "Plain Text" => Some(fence_tag(syntax)),
+        _ => extension.map(|ext| ext.to_lowercase()),
+    }
+}
+
+/// Code fence tag for a syntax
+fn fence_tag(syntax: &SyntaxReference) -> String {
+    match FENCE_TAGS.iter().find(|(name, _)| *name == syntax.name) {
+        Some((_, tag)) => tag.to_string(),
+        None => syntax.name.to_lowercase().replace(' ', "-"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let detect = |rel, content| detect(&syntax_set, rel, content);
+        assert_eq!(detect("src/main.rs", "").as_deref(), Some("rust"));
+        assert_eq!(detect("lib/util.cpp", "").as_deref(), Some("cpp"));
+        assert_eq!(detect("Makefile", "").as_deref(), Some("makefile"));
+        assert_eq!(detect("bin/run", "#!/bin/bash\necho hi\n").as_deref(), Some("bash"));
+        assert_eq!(detect("Cargo.toml", "").as_deref(), Some("toml"));
+        assert_eq!(detect("notes.txt", "hello").as_deref(), Some("txt"));
+        assert_eq!(detect("LICENSE", "text"), None);
+    }
+}
@@ -1,92 +1,667 @@
+mod archive;
+mod batch;
+mod cache;
+mod changes;
+mod credentials;
+mod diff_view;
 mod file_analyzer;
 mod git_ops;
 mod html_builder;
+mod ignore_rules;
+mod language;
+mod manifest;
+mod markdown_gen;
+mod site_gen;
+mod source;
+mod submodules;
+mod tokens;
 mod tree_gen;
 mod cxml_gen;
 mod utils;
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use source::{detect_source, RepoSource};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::{Path, PathBuf};
 use tempfile::TempDir;
 
-enum RepoSource {
-    Local(PathBuf),
-    Remote(String),
-}
-
-fn detect_source(input: &str) -> RepoSource {
-    let path = Path::new(input);
-    
-    // Check if it's a local path that exists
-    if path.exists() && path.is_dir() {
-        RepoSource::Local(path.to_path_buf())
-    } else if input.starts_with("http://") || input.starts_with("https://") || input.starts_with("git@") {
-        RepoSource::Remote(input.to_string())
-    } else {
-        // Try as local path first, even if it doesn't exist yet
-        RepoSource::Local(PathBuf::from(input))
-    }
-}
-
 /// Flatten a GitHub repo into a single static HTML page for fast skimming and search
 #[derive(Parser, Debug)]
 #[command(
     author,
     version,
     about = "Flatten a GitHub repo into a single static HTML page",
-    long_about = "A Rust implementation of rendergit that flattens any GitHub repository or local directory into a single, static HTML page with syntax highlighting, markdown rendering, and sidebar navigation. Perfect for code review, exploration, and an instant Ctrl+F experience."
+    long_about = "A Rust implementation of rendergit that flattens any GitHub repository or local directory into a single, static HTML page with syntax highlighting, markdown rendering, and sidebar navigation. Perfect for code review, exploration, and an instant Ctrl+F experience.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
 )]
 struct Args {
-    /// GitHub repository URL (https://github.com/owner/repo[.git]) or local directory path
-    repo_url_or_path: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Repository URL (https, ssh or git@host:path), shorthand (owner/repo,
+    /// github:, gitlab:, codeberg:) or local directory path (optionally file://).
+    /// Several sources are combined into one page.
+    #[arg(required = true, value_name = "REPO_URL_OR_PATH")]
+    repo_url_or_path: Vec<String>,
 
-    /// Output HTML file path (default: temporary file derived from repo name)
+    /// Output file path, or `-` for stdout (default: temporary file derived from repo name)
     #[arg(short, long)]
     out: Option<PathBuf>,
 
-    /// Maximum file size to render in bytes (larger files are listed but skipped)
-    #[arg(long, default_value_t = 50 * 1024)]
-    max_bytes: usize,
+    /// Output format: an HTML page, a single Markdown file, just the CXML document,
+    /// or a multi-page static site written to the -o directory
+    #[arg(long, value_enum, default_value_t = OutputFormat::Html)]
+    format: OutputFormat,
 
     /// Don't open the HTML file in browser after generation
     #[arg(long)]
     no_open: bool,
 
-    /// Clone a specific branch instead of default branch
+    /// Only render changed files of a local working copy: uncommitted (working),
+    /// staged, or changed since a revision (since:<rev>)
+    #[arg(long, value_name = "working|staged|since:REV")]
+    changes: Option<changes::ChangeSet>,
+
+    /// Only render the paths listed in this file, one per line (`-` reads stdin,
+    /// e.g. `git ls-files | easycopy . --files-from -`)
+    #[arg(long, value_name = "-|PATH")]
+    files_from: Option<PathBuf>,
+
+    /// Render the changes between two revisions as a diff page instead of the files
+    #[arg(long, value_name = "BASE..HEAD")]
+    diff: Option<diff_view::DiffRange>,
+
+    /// Layout of --diff pages
+    #[arg(long, value_enum, default_value_t = diff_view::DiffLayout::Unified)]
+    diff_layout: diff_view::DiffLayout,
+
+    /// Also write a JSON manifest of the render: sources, refs, options, every
+    /// file with its status, language, line count and hash, and totals
+    #[arg(long, value_name = "PATH")]
+    manifest: Option<PathBuf>,
+
+    #[command(flatten)]
+    options: RenderOptions,
+}
+
+/// Kind of document to write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+enum OutputFormat {
+    /// HTML page with human and LLM views
+    #[default]
+    Html,
+    /// Markdown file with fenced code blocks
+    Markdown,
+    /// The CXML document of the LLM view, without any HTML
+    Cxml,
+    /// A directory with an index page, one page per file and shared assets
+    Site,
+}
+
+impl OutputFormat {
+    /// Default output file (or directory, for a site) named after `stem`
+    fn default_output(self, stem: &str) -> PathBuf {
+        let name = match self {
+            Self::Html => format!("{}.html", stem),
+            Self::Markdown => format!("{}.md", stem),
+            Self::Cxml => format!("{}.xml", stem),
+            Self::Site => format!("{}-site", stem),
+        };
+        std::env::temp_dir().join(name)
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Html => "HTML",
+            Self::Markdown => "Markdown",
+            Self::Cxml => "CXML",
+            Self::Site => "static site",
+        }
+    }
+}
+
+/// Options shared by single renders and `easycopy batch`
+#[derive(clap::Args, Debug)]
+struct RenderOptions {
+    /// Maximum file size to render in bytes (larger files are listed but skipped)
+    #[arg(long, default_value_t = 50 * 1024)]
+    max_bytes: usize,
+
+    /// Render a specific branch instead of the default branch
     #[arg(short, long)]
     branch: Option<String>,
 
-    /// Clone a specific tag instead of default branch
+    /// Render a specific tag instead of the default branch
     #[arg(short, long)]
     tag: Option<String>,
 
-    /// Checkout a specific commit hash
+    /// Render a specific commit (local repos accept any revspec, e.g. HEAD~5)
     #[arg(short, long)]
     commit: Option<String>,
 
+    /// Render the head of a pull request (GitHub) or merge request (GitLab)
+    #[arg(long, value_name = "N")]
+    pr: Option<u64>,
+
+    /// Clone without a working tree and render straight from git objects
+    /// (faster and uses less disk; local bare repos and mirrors are always read this way)
+    #[arg(long)]
+    bare: bool,
+
+    /// Fetch full history instead of a shallow (depth 1) clone
+    #[arg(long)]
+    full_clone: bool,
+
+    /// Keep remote clones in a persistent cache and fetch incrementally on
+    /// later runs (see `easycopy cache`)
+    #[arg(long)]
+    cache: bool,
+
     /// Disable progress indicators (useful for piping output)
     #[arg(long)]
     no_progress: bool,
+
+    /// Render files matched by .gitignore, .ignore and global git excludes
+    #[arg(long)]
+    no_ignore: bool,
+
+    /// Only render files matching this glob (repeatable, e.g. 'src/**/*.rs')
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip files matching this glob (repeatable, e.g. '**/fixtures/**')
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Render submodules recursively: checked-out ones as they are on disk,
+    /// the others at their pinned commits (the checkout is not changed).
+    /// Not with --changes or --files-from
+    #[arg(long)]
+    submodules: bool,
+
+    /// Only scan and render this directory of the repository (paths stay repo-relative;
+    /// remote clones check out just this directory)
+    #[arg(long, value_name = "PATH")]
+    subdir: Option<String>,
+
+    /// How to handle symlinks: skip them, list them with their targets, or follow
+    /// links that stay inside the repository
+    #[arg(long, value_enum, default_value_t = file_analyzer::SymlinkPolicy::Skip)]
+    symlinks: file_analyzer::SymlinkPolicy,
+
+    /// Tokenizer for the token counts shown per file and in the stats
+    /// [default: o200k with --token-budget, estimate otherwise]
+    #[arg(long, value_enum)]
+    tokenizer: Option<tokens::Tokenizer>,
+
+    /// Keep the CXML document within this many tokens: files are taken in
+    /// --budget-order while they fit, and the rest are listed as omitted
+    #[arg(long, value_name = "N")]
+    token_budget: Option<usize>,
+
+    /// Which files --token-budget takes first: path order, smallest first, or
+    /// an overview (READMEs, docs and build manifests, then shallow paths)
+    #[arg(long, value_enum, default_value_t = tokens::BudgetOrder::Path)]
+    budget_order: tokens::BudgetOrder,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage the persistent clone cache used by --cache (render a local
+    /// directory named `cache` or `batch` as `./cache` or `./batch`)
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Render many sources, each to its own page, plus an index.html linking them
+    Batch {
+        /// File with one source per line (blank lines and # comments are skipped);
+        /// read from stdin when omitted or `-`
+        list: Option<PathBuf>,
+
+        /// Directory for the pages and index.html
+        #[arg(short, long, default_value = "easycopy-batch")]
+        out_dir: PathBuf,
+
+        #[command(flatten)]
+        options: Box<RenderOptions>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheAction {
+    /// List cached repositories
+    List,
+    /// Remove entries that have not been used recently
+    Prune {
+        /// Remove entries unused for more than this many days
+        #[arg(long, default_value_t = 30)]
+        older_than: u64,
+    },
+    /// Remove all cached repositories
+    Clear,
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    // Validate conflicting options
-    let ref_count = [args.branch.is_some(), args.tag.is_some(), args.commit.is_some()]
+    match args.command {
+        Some(Command::Cache { action }) => run_cache_command(action),
+        Some(Command::Batch { list, out_dir, options }) => run_batch(list.as_deref(), &out_dir, &options),
+        None => render(args),
+    }
+}
+
+/// Handle `easycopy cache ...`
+fn run_cache_command(action: CacheAction) -> Result<()> {
+    let root = cache::default_root()?;
+
+    match action {
+        CacheAction::List => {
+            let entries = cache::list(&root)?;
+            eprintln!("📦 Cache directory: {} ({} entries)", root.display(), entries.len());
+            for entry in &entries {
+                println!("{}", cache::describe(entry));
+            }
+        }
+        CacheAction::Prune { older_than } => {
+            let max_age = std::time::Duration::from_secs(older_than * 24 * 60 * 60);
+            let removed = cache::prune(&root, max_age)?;
+            let freed: u64 = removed.iter().map(|e| e.size).sum();
+            eprintln!("🗑️  Pruned {} entries ({})", removed.len(), utils::bytes_human(freed));
+        }
+        CacheAction::Clear => {
+            let removed = cache::clear(&root)?;
+            let freed: u64 = removed.iter().map(|e| e.size).sum();
+            eprintln!("🗑️  Cleared {} entries ({})", removed.len(), utils::bytes_human(freed));
+        }
+    }
+    Ok(())
+}
+
+/// Settings shared by every source of a run
+struct RenderPlan {
+    scan_options: file_analyzer::ScanOptions,
+    clone_options: git_ops::CloneOptions,
+    git_ref: git_ops::GitRef,
+    /// Clone remotes without a working tree (--bare, --cache, --diff)
+    remote_from_objects: bool,
+    cache: bool,
+    submodules: bool,
+    changes: Option<changes::ChangeSet>,
+    /// Paths from --files-from, relative to the repository root
+    files_from: Option<Vec<String>>,
+    show_progress: bool,
+    tokens: tokens::TokenOptions,
+}
+
+impl RenderPlan {
+    /// Check the ref flags and build the scan and clone options. A diff
+    /// needs full history and every blob.
+    fn new(options: &RenderOptions, changes: Option<changes::ChangeSet>, diff: bool) -> Result<Self> {
+        // Validate conflicting options
+        if ref_count(options) > 1 {
+            anyhow::bail!("Cannot specify more than one of --branch, --tag, --commit, or --pr");
+        }
+
+        let scan_options = file_analyzer::ScanOptions {
+            max_bytes: options.max_bytes,
+            respect_ignore: !options.no_ignore,
+            filter: file_analyzer::PathFilter::new(&options.include, &options.exclude)?,
+            subdir: match &options.subdir {
+                Some(subdir) => file_analyzer::normalize_subdir(subdir)?,
+                None => None,
+            },
+            symlinks: options.symlinks,
+        };
+
+        // A diff needs the history between both revisions and every blob in it
+        let clone_options = git_ops::CloneOptions {
+            shallow: !options.full_clone && !diff,
+            sparse_path: scan_options.subdir.clone(),
+            // Blobs over --max-bytes are never rendered, so skip downloading them
+            blob_limit: (!diff).then_some(options.max_bytes as u64),
+            parent_url: None,
+        };
+
+        // Determine git reference to use
+        let git_ref = if let Some(branch) = &options.branch {
+            git_ops::GitRef::Branch(branch.clone())
+        } else if let Some(tag) = &options.tag {
+            git_ops::GitRef::Tag(tag.clone())
+        } else if let Some(commit) = &options.commit {
+            git_ops::GitRef::Commit(commit.clone())
+        } else if let Some(number) = options.pr {
+            git_ops::GitRef::PullRequest(number)
+        } else {
+            git_ops::GitRef::Default
+        };
+
+        Ok(Self {
+            scan_options,
+            clone_options,
+            git_ref,
+            remote_from_objects: options.bare || options.cache || diff,
+            cache: options.cache,
+            submodules: options.submodules,
+            changes,
+            files_from: None,
+            show_progress: !options.no_progress,
+            tokens: tokens::TokenOptions {
+                tokenizer: tokens::Tokenizer::resolve(options.tokenizer, options.token_budget),
+                budget: options.token_budget,
+                order: options.budget_order,
+            },
+        })
+    }
+}
+
+/// How many of --branch, --tag, --commit and --pr are set
+fn ref_count(options: &RenderOptions) -> usize {
+    [options.branch.is_some(), options.tag.is_some(), options.commit.is_some(), options.pr.is_some()]
         .iter()
         .filter(|&&x| x)
-        .count();
-    
-    if ref_count > 1 {
-        anyhow::bail!("Cannot specify more than one of --branch, --tag, or --commit");
+        .count()
+}
+
+/// A source checked out or located on disk
+struct FetchedRepo {
+    repo_dir: PathBuf,
+    temp_dir: Option<TempDir>,
+    /// Held until the cached clone has been rendered
+    cache_lock: Option<cache::EntryLock>,
+    name: String,
+    /// Read from the object database rather than a working tree
+    from_objects: bool,
+}
+
+/// A source with its files collected, ready to render
+struct ScannedRepo {
+    input: String,
+    name: String,
+    head_commit: String,
+    /// Branch HEAD pointed at, for the manifest
+    head_branch: Option<String>,
+    file_infos: Vec<file_analyzer::FileInfo>,
+    submodule_infos: Vec<submodules::SubmoduleInfo>,
+    temp_dir: Option<TempDir>,
+    /// Rendered files are read again from the cached clone
+    _cache_lock: Option<cache::EntryLock>,
+}
+
+impl ScannedRepo {
+    fn section(&self) -> html_builder::RepoSection<'_> {
+        html_builder::RepoSection {
+            repo_url: &self.input,
+            repo_name: &self.name,
+            head_commit: &self.head_commit,
+            file_infos: &self.file_infos,
+            submodules: &self.submodule_infos,
+        }
+    }
+}
+
+/// Render one or more repositories to HTML
+fn render(args: Args) -> Result<()> {
+    if args.diff.is_some() && args.repo_url_or_path.len() > 1 {
+        anyhow::bail!("--diff renders a single repository");
+    }
+    if args.diff.is_some() && matches!(args.format, OutputFormat::Markdown | OutputFormat::Site) {
+        anyhow::bail!("--diff is only available as HTML or CXML");
+    }
+    if args.format == OutputFormat::Site && args.out.as_deref() == Some(Path::new("-")) {
+        anyhow::bail!("--format site writes a directory, so it cannot be written to stdout");
+    }
+    if args.diff.is_some() && args.manifest.is_some() {
+        anyhow::bail!("--manifest describes rendered files, so it cannot be used with --diff");
+    }
+    if args.options.token_budget.is_some() && (args.diff.is_some() || args.format == OutputFormat::Markdown) {
+        anyhow::bail!("--token-budget limits the CXML document of rendered files, so it cannot be used with --diff or --format markdown");
+    }
+    if args.options.submodules && (args.changes.is_some() || args.files_from.is_some()) {
+        anyhow::bail!("--submodules cannot be combined with --changes or --files-from");
+    }
+    let ref_count = ref_count(&args.options);
+    let mut plan = RenderPlan::new(&args.options, args.changes.clone(), args.diff.is_some())?;
+
+    if let Some(list) = &args.files_from {
+        if args.repo_url_or_path.len() > 1 || args.diff.is_some() || args.changes.is_some() {
+            anyhow::bail!("--files-from takes a single source and cannot be combined with --diff or --changes");
+        }
+        let text = utils::read_text_or_stdin(list).context("Failed to read --files-from list")?;
+        plan.files_from = Some(
+            text.lines()
+                .map(|line| line.trim_end_matches('\r'))
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+        );
+    }
+
+    // Detect if each input is local or remote, and check it fits the options
+    let mut sources = Vec::new();
+    let mut cache_entries = std::collections::HashSet::new();
+    for input in &args.repo_url_or_path {
+        let source = detect_source(input);
+
+        if args.diff.is_some() {
+            if matches!(source, RepoSource::Archive(_)) {
+                anyhow::bail!("--diff needs a git repository, not an archive");
+            }
+            if ref_count > 0 || args.changes.is_some() {
+                anyhow::bail!("--diff cannot be combined with --branch, --tag, --commit, --pr or --changes");
+            }
+        }
+
+        if args.changes.is_some() {
+            if !matches!(source, RepoSource::Local(_)) {
+                anyhow::bail!("--changes only works with a local working copy");
+            }
+            if ref_count > 0 {
+                anyhow::bail!("--changes cannot be combined with --branch, --tag, --commit or --pr (use --changes since:<rev>)");
+            }
+        }
+
+        // A cached clone stays locked until it is rendered, so a second
+        // fetch of the same entry would wait forever
+        if let RepoSource::Remote(url) = &source {
+            if plan.cache && !cache_entries.insert(cache::entry_name(url)) {
+                anyhow::bail!("{} is given more than once; a cached repository can only be rendered once per run", input);
+            }
+        }
+
+        // Checked before anything is cloned or fetched
+        if plan.files_from.is_some() && renders_from_objects(&source, &plan) {
+            anyhow::bail!("--files-from needs a working tree, so it cannot be used with --bare, --cache, a bare repository or a ref of a local one");
+        }
+        sources.push((input.as_str(), source));
+    }
+
+    if let Some(range) = &args.diff {
+        let (input, source) = sources.remove(0);
+        let fetched = fetch_source(source, &plan)?;
+        let output_path = args.out.clone().unwrap_or_else(|| args.format.default_output(&fetched.name));
+        let content = render_diff(&fetched.repo_dir, input, range, &plan.scan_options, args.diff_layout, args.format)?;
+        return write_output(
+            content,
+            &output_path,
+            args.format,
+            args.no_open,
+            fetched.temp_dir.into_iter().collect(),
+        );
+    }
+
+    let mut repos = Vec::new();
+    for (input, source) in sources {
+        let fetched = fetch_source(source, &plan)?;
+        repos.push(scan_repo(input, fetched, &plan)?);
+    }
+
+    // Repos with the same name (e.g. two `app` folders) get a numbered suffix
+    // so their anchors and CXML sources stay apart
+    let mut names: Vec<_> = repos.iter().map(|r| r.name.clone()).collect();
+    utils::dedupe_names(&mut names);
+    for (repo, name) in repos.iter_mut().zip(names) {
+        repo.name = name;
+    }
+
+    // Determine output path
+    let output_path = args.out.clone().unwrap_or_else(|| {
+        let names: Vec<_> = repos.iter().map(|r| r.name.as_str()).collect();
+        args.format.default_output(&names.join("+"))
+    });
+
+    // Written while the clones still exist, since rendered files are read again
+    if let Some(path) = &args.manifest {
+        let repos_with_refs = repos
+            .iter()
+            .map(|r| (r.section(), manifest::RefInfo::new(&plan.git_ref, r.head_branch.clone())))
+            .collect();
+        let manifest = manifest::build_manifest(repos_with_refs, manifest_options(&args))
+            .context("Failed to build manifest")?;
+        let json = serde_json::to_string_pretty(&manifest).context("Failed to serialize manifest")?;
+        std::fs::write(path, json + "\n")
+            .with_context(|| format!("Failed to write manifest: {}", path.display()))?;
+        eprintln!("📋 Saved manifest to {}", path.display());
+    }
+
+    eprintln!("🔨 Generating {}...", args.format.label());
+    let sections: Vec<_> = repos.iter().map(ScannedRepo::section).collect();
+    if args.format == OutputFormat::Site {
+        let files = site_gen::build_site(&sections, &plan.tokens).context("Failed to build static site")?;
+        let temp_dirs = repos.into_iter().filter_map(|r| r.temp_dir).collect();
+        return write_site(&output_path, files, args.no_open, temp_dirs);
+    }
+
+    let content = match (args.format, repos.as_slice()) {
+        (OutputFormat::Html, [repo]) => build_page(repo, &plan.tokens),
+        (OutputFormat::Html, _) => html_builder::build_combined_html(&sections, &plan.tokens),
+        (OutputFormat::Markdown, _) => markdown_gen::generate_markdown(&sections),
+        (OutputFormat::Cxml, [repo]) => cxml_gen::generate_cxml(&repo.file_infos, &plan.tokens),
+        (OutputFormat::Cxml, _) => {
+            let cxml_repos: Vec<_> = sections.iter().map(|s| (s.repo_name, s.file_infos)).collect();
+            cxml_gen::generate_combined_cxml(&cxml_repos, &plan.tokens)
+        }
+        (OutputFormat::Site, _) => unreachable!("sites are written above"),
+    }
+    .with_context(|| format!("Failed to build {}", args.format.label()))?;
+
+    let temp_dirs = repos.into_iter().filter_map(|r| r.temp_dir).collect();
+    write_output(content, &output_path, args.format, args.no_open, temp_dirs)
+}
+
+/// Options recorded in the manifest, as given on the command line
+fn manifest_options(args: &Args) -> serde_json::Value {
+    let options = &args.options;
+    serde_json::json!({
+        "format": value_name(args.format),
+        "max_bytes": options.max_bytes,
+        "branch": options.branch,
+        "tag": options.tag,
+        "commit": options.commit,
+        "pr": options.pr,
+        "bare": options.bare,
+        "full_clone": options.full_clone,
+        "cache": options.cache,
+        "no_ignore": options.no_ignore,
+        "include": options.include,
+        "exclude": options.exclude,
+        "subdir": options.subdir,
+        "submodules": options.submodules,
+        "symlinks": value_name(options.symlinks),
+        "tokenizer": options.tokenizer.and_then(value_name),
+        "token_budget": options.token_budget,
+        "budget_order": value_name(options.budget_order),
+        "changes": args.changes.as_ref().map(ToString::to_string),
+        "files_from": args.files_from,
+    })
+}
+
+/// Name of a value as spelled on the command line
+fn value_name(value: impl ValueEnum) -> Option<String> {
+    value.to_possible_value().map(|v| v.get_name().to_string())
+}
+
+/// Build the page of a single repository
+fn build_page(repo: &ScannedRepo, tokens: &tokens::TokenOptions) -> Result<String> {
+    let section = repo.section();
+    html_builder::build_html(
+        section.repo_url,
+        section.repo_name,
+        section.head_commit,
+        section.file_infos,
+        section.submodules,
+        tokens,
+    )
+}
+
+/// Handle `easycopy batch`: render every listed source to its own page in
+/// `out_dir` (see `batch::render_all`)
+fn run_batch(list: Option<&Path>, out_dir: &Path, options: &RenderOptions) -> Result<()> {
+    let plan = RenderPlan::new(options, None, false)?;
+    let sources = batch::read_source_list(list)?;
+    batch::render_all(&sources, out_dir, |input, used_names| {
+        render_batch_page(input, out_dir, &plan, used_names)
+    })
+}
+
+/// Fetch, scan and write one source of a batch. `used_names` keeps file
+/// names unique across the batch.
+fn render_batch_page(
+    input: &str,
+    out_dir: &Path,
+    plan: &RenderPlan,
+    used_names: &mut Vec<String>,
+) -> Result<batch::BatchPage> {
+    let fetched = fetch_source(detect_source(input), plan)?;
+    let repo = scan_repo(input, fetched, plan)?;
+    let html_content = build_page(&repo, &plan.tokens).context("Failed to build HTML")?;
+
+    let file_name = batch::page_file_name(&repo.name, used_names);
+    let path = out_dir.join(&file_name);
+    std::fs::write(&path, &html_content)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    eprintln!("✓ Wrote {} to {}", utils::bytes_human(html_content.len() as u64), path.display());
+
+    Ok(batch::BatchPage {
+        name: repo.name,
+        file_name,
+        head_commit: repo.head_commit,
+        rendered_files: repo.file_infos.iter().filter(|f| f.decision.include).count(),
+        total_files: repo.file_infos.len(),
+        html_size: html_content.len() as u64,
+    })
+}
+
+/// Render from the object database instead of a working tree when one is
+/// not wanted (--bare), does not exist (bare repos and mirrors), or must be
+/// left untouched (a ref on a local source)
+fn renders_from_objects(source: &RepoSource, plan: &RenderPlan) -> bool {
+    match source {
+        RepoSource::Local(path) => {
+            !matches!(plan.git_ref, git_ops::GitRef::Default) || git_ops::is_bare_repo(path)
+        }
+        RepoSource::Remote(_) => plan.remote_from_objects,
+        RepoSource::Archive(_) => false,
     }
+}
+
+/// Locate a local source, unpack an archive, or clone a remote repository
+fn fetch_source(source: RepoSource, plan: &RenderPlan) -> Result<FetchedRepo> {
+    let (git_ref, clone_options) = (&plan.git_ref, &plan.clone_options);
+    let ref_desc = match git_ref {
+        git_ops::GitRef::Branch(b) => format!(" (branch: {})", b),
+        git_ops::GitRef::Tag(t) => format!(" (tag: {})", t),
+        git_ops::GitRef::Commit(c) => format!(" (commit: {})", utils::short_id(c)),
+        git_ops::GitRef::PullRequest(n) => format!(" (pull request #{})", n),
+        git_ops::GitRef::Default => String::new(),
+    };
+
+    let from_objects = renders_from_objects(&source, plan);
 
-    // Detect if input is local or remote
-    let source = detect_source(&args.repo_url_or_path);
-    
-    let (repo_dir, temp_dir, repo_name) = match source {
+    let mut cache_lock = None;
+    let (repo_dir, temp_dir, name) = match source {
         RepoSource::Local(path) => {
             if !path.exists() {
                 anyhow::bail!("Local directory does not exist: {}", path.display());
@@ -95,85 +670,310 @@ fn main() -> Result<()> {
                 anyhow::bail!("Path is not a directory: {}", path.display());
             }
             
-            eprintln!("📂 Using local directory: {}", path.display());
+            eprintln!("📂 Using local directory: {}{}", path.display(), ref_desc);
             let name = path.file_name()
                 .and_then(|n| n.to_str())
                 .unwrap_or("local")
                 .to_string();
             (path.clone(), None, name)
         }
-        RepoSource::Remote(url) => {
-            // Determine git reference to use
-            let git_ref = if let Some(branch) = &args.branch {
-                git_ops::GitRef::Branch(branch.clone())
-            } else if let Some(tag) = &args.tag {
-                git_ops::GitRef::Tag(tag.clone())
-            } else if let Some(commit) = &args.commit {
-                git_ops::GitRef::Commit(commit.clone())
-            } else {
-                git_ops::GitRef::Default
-            };
+        RepoSource::Archive(path) => {
+            if !matches!(git_ref, git_ops::GitRef::Default) {
+                anyhow::bail!("--branch, --tag, --commit and --pr cannot be used with an archive");
+            }
 
-            let ref_desc = match &git_ref {
-                git_ops::GitRef::Branch(b) => format!(" (branch: {})", b),
-                git_ops::GitRef::Tag(t) => format!(" (tag: {})", t),
-                git_ops::GitRef::Commit(c) => format!(" (commit: {})", &c[..8.min(c.len())]),
-                git_ops::GitRef::Default => String::new(),
-            };
+            let temp = TempDir::new().context("Failed to create temporary directory")?;
+            let repo_path = temp.path().join("repo");
+            eprintln!("📦 Unpacking {} to temporary directory: {}", path.display(), repo_path.display());
+            archive::extract(&path, &repo_path).context("Failed to unpack archive")?;
+
+            (repo_path, Some(temp), archive::archive_name(&path))
+        }
+        RepoSource::Remote(url) if plan.cache => {
+            let root = cache::default_root()?;
+            eprintln!("📦 Fetching {}{} into cache: {}", url, ref_desc, root.display());
+            let (repo_path, lock) = cache::fetch_cached(&root, &url, git_ref, clone_options.shallow)
+                .context("Failed to update cached clone")?;
+            cache_lock = Some(lock);
 
+            let name = utils::derive_repo_name(&url);
+            (repo_path, None, name)
+        }
+        RepoSource::Remote(url) => {
             // Create temporary directory for cloning
             let temp = TempDir::new().context("Failed to create temporary directory")?;
             let repo_path = temp.path().join("repo");
 
-            eprintln!("📁 Cloning {}{} to temporary directory: {}", url, ref_desc, repo_path.display());
-            git_ops::clone_repo(&url, &repo_path, &git_ref)
-                .context("Failed to clone repository")?;
+            if from_objects {
+                eprintln!("📁 Cloning {}{} (bare) to temporary directory: {}", url, ref_desc, repo_path.display());
+                git_ops::clone_bare(&url, &repo_path, git_ref, clone_options)
+                    .context("Failed to clone repository")?;
+            } else {
+                eprintln!("📁 Cloning {}{} to temporary directory: {}", url, ref_desc, repo_path.display());
+                git_ops::clone_repo(&url, &repo_path, git_ref, clone_options)
+                    .context("Failed to clone repository")?;
+            }
 
             let name = utils::derive_repo_name(&url);
             (repo_path, Some(temp), name)
         }
     };
 
-    // Determine output path
-    let output_path = args.out.unwrap_or_else(|| {
-        let filename = format!("{}.html", repo_name);
-        std::env::temp_dir().join(filename)
-    });
+    Ok(FetchedRepo {
+        repo_dir,
+        temp_dir,
+        cache_lock,
+        name,
+        from_objects,
+    })
+}
+
+/// Collect the files (and submodules) of a fetched source
+fn scan_repo(input: &str, fetched: FetchedRepo, plan: &RenderPlan) -> Result<ScannedRepo> {
+    let (git_ref, scan_options, clone_options) = (&plan.git_ref, &plan.scan_options, &plan.clone_options);
+    let FetchedRepo { repo_dir, temp_dir, cache_lock, name, from_objects } = fetched;
+
+    // Directory the tree is drawn from; paths in the output stay repo-relative
+    let scan_dir = match &scan_options.subdir {
+        Some(subdir) => repo_dir.join(subdir),
+        None => repo_dir.clone(),
+    };
+
+    let (head_commit, head_branch, file_infos, submodule_infos) = if from_objects {
+        let repo = git_ops::open_repo(&repo_dir)?;
+        let commit = git_ops::resolve_commit(&repo, git_ref)?;
+        let head_commit = commit.id().to_string();
+        let head_branch = git_ops::head_branch(&repo);
+        eprintln!("✓ Revision resolved (commit: {})", utils::short_id(&head_commit));
+
+        eprintln!("📊 Reading files from git objects...");
+        let tree = commit.tree().context("Failed to read commit tree")?;
+        let (submodule_infos, submodule_files) = if plan.submodules {
+            let work_dir = TempDir::new().context("Failed to create temporary directory")?;
+            submodules::collect_tree_submodules(
+                &repo,
+                &tree,
+                "",
+                scan_options,
+                clone_options,
+                work_dir.path(),
+                plan.show_progress,
+            )
+            .context("Failed to read submodules")?
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        // A subdir inside a submodule has no files in the superproject's tree
+        let mut infos = if submodules::contains_subdir(&submodule_infos, scan_options.subdir.as_deref()) {
+            Vec::new()
+        } else {
+            file_analyzer::collect_tree_files(&repo, &tree, scan_options, plan.show_progress)
+                .context("Failed to collect files")?
+        };
+        infos.extend(submodule_files);
+        infos.sort_by(|a, b| a.rel.cmp(&b.rel));
+        (head_commit, head_branch, infos, submodule_infos)
+    } else {
+        let (submodule_infos, submodule_files) = if plan.submodules {
+            let work_dir = TempDir::new().context("Failed to create temporary directory")?;
+            submodules::collect_worktree_submodules(
+                &repo_dir,
+                "",
+                scan_options,
+                clone_options,
+                work_dir.path(),
+                plan.show_progress,
+            )
+            .context("Failed to read submodules")?
+        } else {
+            (Vec::new(), Vec::new())
+        };
 
-    let head_commit = git_ops::get_head_commit(&repo_dir)
-        .unwrap_or_else(|_| "(unknown)".to_string());
-    eprintln!("✓ Repository ready (HEAD: {})", &head_commit[..8.min(head_commit.len())]);
+        let head_commit = git_ops::get_head_commit(&repo_dir)
+            .unwrap_or_else(|_| "(unknown)".to_string());
+        let head_branch = git_ops::open_repo(&repo_dir).ok().and_then(|repo| git_ops::head_branch(&repo));
+        eprintln!("✓ Repository ready (HEAD: {})", utils::short_id(&head_commit));
 
-    eprintln!("📊 Scanning files in {}...", repo_dir.display());
-    let file_infos = file_analyzer::collect_files(&repo_dir, args.max_bytes, !args.no_progress)
-        .context("Failed to collect files")?;
+        let infos = match (&plan.changes, &plan.files_from) {
+            (Some(changes), _) => {
+                eprintln!("📊 Collecting changes ({}) in {}...", changes, scan_dir.display());
+                changes::collect_changed_files(&repo_dir, changes, scan_options)
+                    .context("Failed to collect changed files")?
+            }
+            (None, Some(paths)) => {
+                eprintln!("📊 Checking {} listed paths in {}...", paths.len(), repo_dir.display());
+                file_analyzer::collect_listed_files(&repo_dir, paths, scan_options)
+                    .context("Failed to collect listed files")?
+            }
+            (None, None) => {
+                eprintln!("📊 Scanning files in {}...", scan_dir.display());
+                // Only a checked-out submodule has the subdir on disk
+                let in_submodule = submodules::contains_subdir(&submodule_infos, scan_options.subdir.as_deref());
+                let mut infos = if in_submodule && !scan_dir.is_dir() {
+                    Vec::new()
+                } else {
+                    file_analyzer::collect_files(&repo_dir, scan_options, plan.show_progress)
+                        .context("Failed to collect files")?
+                };
+                infos.extend(submodule_files);
+                infos.sort_by(|a, b| a.rel.cmp(&b.rel));
+                infos
+            }
+        };
+        (head_commit, head_branch, infos, submodule_infos)
+    };
 
-    let rendered_count = file_infos.iter().filter(|f| f.decision.include).count();
-    let skipped_count = file_infos.len() - rendered_count;
-    eprintln!("✓ Found {} files total ({} will be rendered, {} skipped)",
-              file_infos.len(), rendered_count, skipped_count);
+    let counts = file_analyzer::FileCounts::of(&file_infos);
+    let deleted = if counts.deleted > 0 {
+        format!(", {} deleted", counts.deleted)
+    } else {
+        String::new()
+    };
+    eprintln!("✓ Found {} files total ({} will be rendered, {} skipped{})",
+              counts.total, counts.rendered, counts.skipped, deleted);
+
+    Ok(ScannedRepo {
+        input: input.to_string(),
+        name,
+        head_commit,
+        head_branch,
+        file_infos,
+        submodule_infos,
+        temp_dir,
+        _cache_lock: cache_lock,
+    })
+}
+
+/// Diff two revisions of the repository at `repo_dir` and build the diff
+/// page, or just its structured diff for `--format cxml`
+fn render_diff(
+    repo_dir: &Path,
+    input: &str,
+    range: &diff_view::DiffRange,
+    scan_options: &file_analyzer::ScanOptions,
+    layout: diff_view::DiffLayout,
+    format: OutputFormat,
+) -> Result<String> {
+    let repo = git_ops::open_repo(repo_dir)?;
+    let base = diff_view::resolve_rev(&repo, &range.base)?;
+    let head = diff_view::resolve_rev(&repo, &range.head)?;
+    let (base_id, head_id) = (base.id().to_string(), head.id().to_string());
+    eprintln!("📊 Diffing {} ({}) → {} ({})...", range.base, utils::short_id(&base_id), range.head, utils::short_id(&head_id));
+
+    let files = diff_view::collect_diff(&repo, &base, &head, scan_options)
+        .context("Failed to diff revisions")?;
+    let additions: usize = files.iter().map(|f| f.additions).sum();
+    let deletions: usize = files.iter().map(|f| f.deletions).sum();
+    eprintln!("✓ {} files changed (+{} −{})", files.len(), additions, deletions);
+
+    if format == OutputFormat::Cxml {
+        return Ok(cxml_gen::generate_diff_cxml(&base_id, &head_id, &files));
+    }
 
     eprintln!("🔨 Generating HTML...");
-    let html_content = html_builder::build_html(
-        &args.repo_url_or_path,
-        &repo_dir,
-        &head_commit,
-        &file_infos,
-    ).context("Failed to build HTML")?;
+    html_builder::build_diff_html(input, (&range.base, &base_id), (&range.head, &head_id), &files, layout)
+        .context("Failed to build HTML")
+}
 
-    eprintln!("💾 Writing HTML file: {}", output_path.display());
-    std::fs::write(&output_path, html_content)
-        .context("Failed to write HTML file")?;
+/// Write the pages and assets of a site under `dir`, open its index unless
+/// `--no-open`, and clean up the clones
+fn write_site(dir: &Path, files: Vec<site_gen::SiteFile>, no_open: bool, temp_dirs: Vec<TempDir>) -> Result<()> {
+    clear_site_dir(dir)?;
+    eprintln!("💾 Writing static site: {}", dir.display());
+    let mut total_size = 0;
+    for file in &files {
+        let path = dir.join(&file.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        std::fs::write(&path, &file.content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        total_size += file.content.len() as u64;
+    }
+    eprintln!("✓ Wrote {} files ({}) to {}", files.len(), utils::bytes_human(total_size), dir.display());
+
+    let index = dir.join("index.html");
+    if !no_open {
+        eprintln!("🌐 Opening {} in browser...", index.display());
+        opener::open(&index).ok();
+    }
+
+    for temp in temp_dirs {
+        eprintln!("🗑️  Cleaning up temporary directory: {}", temp.path().display());
+    }
 
-    let file_size = std::fs::metadata(&output_path)?.len();
-    eprintln!("✓ Wrote {} to {}", utils::bytes_human(file_size), output_path.display());
+    Ok(())
+}
+
+/// Make sure `dir` is missing, empty or an earlier site, and remove the
+/// earlier site's files so no stale pages are left. Anything else is refused.
+fn clear_site_dir(dir: &Path) -> Result<()> {
+    let Ok(mut entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+    if entries.next().is_none() {
+        return Ok(());
+    }
+    if !dir.join("index.html").is_file() || !dir.join("assets/nav.js").is_file() {
+        anyhow::bail!(
+            "Output directory {} is not empty and does not hold an easycopy site; choose a new or empty directory",
+            dir.display()
+        );
+    }
+
+    eprintln!("🗑️  Replacing the site in {}", dir.display());
+    for name in ["files", "assets"] {
+        let path = dir.join(name);
+        if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    for name in ["index.html", "llm.txt"] {
+        let path = dir.join(name);
+        if path.is_file() {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the output, open HTML pages unless `--no-open`, and clean up the clones
+fn write_output(
+    content: String,
+    output_path: &Path,
+    format: OutputFormat,
+    no_open: bool,
+    temp_dirs: Vec<TempDir>,
+) -> Result<()> {
+    // `-o -` writes to stdout; progress and status messages stay on stderr
+    let to_stdout = output_path == Path::new("-");
+    if to_stdout {
+        use std::io::Write;
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(content.as_bytes())
+            .and_then(|()| stdout.flush())
+            .context("Failed to write to stdout")?;
+        eprintln!("✓ Wrote {} to stdout", utils::bytes_human(content.len() as u64));
+    } else {
+        eprintln!("💾 Writing {} file: {}", format.label(), output_path.display());
+        std::fs::write(output_path, &content)
+            .with_context(|| format!("Failed to write {} file", format.label()))?;
+
+        let file_size = std::fs::metadata(output_path)?.len();
+        eprintln!("✓ Wrote {} to {}", utils::bytes_human(file_size), output_path.display());
+    }
 
-    if !args.no_open {
+    if format == OutputFormat::Html && !no_open && !to_stdout {
         eprintln!("🌐 Opening {} in browser...", output_path.display());
-        opener::open(&output_path).ok();
+        opener::open(output_path).ok();
     }
 
-    if let Some(temp) = temp_dir {
+    for temp in temp_dirs {
         eprintln!("🗑️  Cleaning up temporary directory: {}", temp.path().display());
     }
 
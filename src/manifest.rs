@@ -0,0 +1,248 @@
+use crate::git_ops::GitRef;
+use crate::html_builder::RepoSection;
+use crate::language;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use syntect::parsing::SyntaxSet;
+
+/// Machine-readable description of a render, written by `--manifest`
+#[derive(Debug, Serialize)]
+pub struct Manifest<'a> {
+    pub generator: String,
+    /// Command line options the render was made with
+    pub options: serde_json::Value,
+    pub repositories: Vec<RepoManifest<'a>>,
+    /// Totals over all repositories
+    pub stats: Stats,
+}
+
+/// One rendered source
+#[derive(Debug, Serialize)]
+pub struct RepoManifest<'a> {
+    pub source: &'a str,
+    pub name: &'a str,
+    #[serde(rename = "ref")]
+    pub git_ref: RefInfo,
+    pub head_commit: &'a str,
+    pub submodules: Vec<SubmoduleEntry<'a>>,
+    pub files: Vec<FileEntry<'a>>,
+    pub stats: Stats,
+}
+
+/// The revision that was asked for. `kind` is `head` when none was, with
+/// `name` set to the branch HEAD pointed at.
+#[derive(Debug, Serialize)]
+pub struct RefInfo {
+    pub kind: &'static str,
+    pub name: Option<String>,
+}
+
+impl RefInfo {
+    pub fn new(git_ref: &GitRef, head_branch: Option<String>) -> Self {
+        match git_ref {
+            GitRef::Branch(branch) => Self { kind: "branch", name: Some(branch.clone()) },
+            GitRef::Tag(tag) => Self { kind: "tag", name: Some(tag.clone()) },
+            GitRef::Commit(rev) => Self { kind: "commit", name: Some(rev.clone()) },
+            GitRef::PullRequest(number) => Self { kind: "pull_request", name: Some(number.to_string()) },
+            GitRef::Default => Self { kind: "head", name: head_branch },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubmoduleEntry<'a> {
+    pub path: &'a str,
+    pub url: &'a str,
+    pub commit: &'a str,
+}
+
+/// A scanned file. Language, line count and hash are only set for
+/// rendered files; the hash is the git blob id of the contents.
+#[derive(Debug, Serialize)]
+pub struct FileEntry<'a> {
+    pub path: &'a str,
+    /// Unknown for blobs left out of a partial clone
+    pub size: Option<u64>,
+    pub rendered: bool,
+    pub reason: &'a str,
+    pub language: Option<String>,
+    pub lines: Option<usize>,
+    pub content_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symlink_target: Option<&'a str>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub total_files: usize,
+    pub rendered_files: usize,
+    pub skipped_files: usize,
+    /// Files deleted in `--changes`; not part of the totals
+    pub deleted_files: usize,
+    pub total_bytes: u64,
+    pub rendered_bytes: u64,
+    pub rendered_lines: usize,
+    /// Skipped files per decision reason, e.g. `too_large` or `binary`
+    pub skipped_by_reason: BTreeMap<String, usize>,
+    /// Rendered files per detected language
+    pub languages: BTreeMap<String, LanguageStats>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LanguageStats {
+    pub files: usize,
+    pub lines: usize,
+    pub bytes: u64,
+}
+
+impl Stats {
+    fn add_file(&mut self, file: &FileEntry) {
+        if file.reason == "deleted" {
+            self.deleted_files += 1;
+            return;
+        }
+        self.total_files += 1;
+        self.total_bytes += file.size.unwrap_or_default();
+        if !file.rendered {
+            self.skipped_files += 1;
+            *self.skipped_by_reason.entry(file.reason.to_string()).or_default() += 1;
+            return;
+        }
+
+        let lines = file.lines.unwrap_or_default();
+        self.rendered_files += 1;
+        self.rendered_bytes += file.size.unwrap_or_default();
+        self.rendered_lines += lines;
+        let language = file.language.as_deref().unwrap_or("unknown");
+        let entry = self.languages.entry(language.to_string()).or_default();
+        entry.files += 1;
+        entry.lines += lines;
+        entry.bytes += file.size.unwrap_or_default();
+    }
+
+    fn merge(&mut self, other: &Stats) {
+        self.total_files += other.total_files;
+        self.rendered_files += other.rendered_files;
+        self.skipped_files += other.skipped_files;
+        self.deleted_files += other.deleted_files;
+        self.total_bytes += other.total_bytes;
+        self.rendered_bytes += other.rendered_bytes;
+        self.rendered_lines += other.rendered_lines;
+        for (reason, count) in &other.skipped_by_reason {
+            *self.skipped_by_reason.entry(reason.clone()).or_default() += count;
+        }
+        for (language, stats) in &other.languages {
+            let entry = self.languages.entry(language.clone()).or_default();
+            entry.files += stats.files;
+            entry.lines += stats.lines;
+            entry.bytes += stats.bytes;
+        }
+    }
+}
+
+/// Build the manifest of the given repositories, each with the ref it was
+/// rendered at. Rendered files are read again for their language, line
+/// count and hash.
+pub fn build_manifest<'a>(
+    repos: Vec<(RepoSection<'a>, RefInfo)>,
+    options: serde_json::Value,
+) -> Result<Manifest<'a>> {
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+    let mut repositories = Vec::new();
+    let mut totals = Stats::default();
+
+    for (repo, git_ref) in repos {
+        let mut stats = Stats::default();
+        let mut files = Vec::new();
+        for info in repo.file_infos {
+            let (language, lines, content_hash) = if info.decision.include {
+                let content = info.read_to_string()?;
+                let hash = git2::Oid::hash_object(git2::ObjectType::Blob, content.as_bytes())
+                    .with_context(|| format!("Failed to hash {}", info.rel))?;
+                (
+                    language::detect(&syntax_set, &info.rel, &content),
+                    Some(content.lines().count()),
+                    Some(hash.to_string()),
+                )
+            } else {
+                (None, None, None)
+            };
+
+            let entry = FileEntry {
+                path: &info.rel,
+                size: (!info.decision.is_not_fetched()).then_some(info.size),
+                rendered: info.decision.include,
+                reason: &info.decision.reason,
+                language,
+                lines,
+                content_hash,
+                symlink_target: info.symlink_target.as_deref(),
+            };
+            stats.add_file(&entry);
+            files.push(entry);
+        }
+        totals.merge(&stats);
+
+        repositories.push(RepoManifest {
+            source: repo.repo_url,
+            name: repo.repo_name,
+            git_ref,
+            head_commit: repo.head_commit,
+            submodules: repo
+                .submodules
+                .iter()
+                .map(|s| SubmoduleEntry { path: &s.path, url: &s.url, commit: &s.commit })
+                .collect(),
+            files,
+            stats,
+        });
+    }
+
+    Ok(Manifest {
+        generator: format!("easycopy {}", env!("CARGO_PKG_VERSION")),
+        options,
+        repositories,
+        stats: totals,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_analyzer::text_file;
+
+    #[test]
+    fn test_build_manifest() {
+        let files = vec![
+            text_file("src/main.rs", "fn main() {}\n", "ok"),
+            text_file("src/lib.rs", "pub mod a;\npub mod b;\n", "ok"),
+            text_file("logo.png", "\u{89}PNG", "binary"),
+            text_file("old.rs", "gone", "deleted"),
+        ];
+        let repo = RepoSection {
+            repo_url: "https://example.com/demo",
+            repo_name: "demo",
+            head_commit: "abc123",
+            file_infos: &files,
+            submodules: &[],
+        };
+        let git_ref = RefInfo::new(&GitRef::Default, Some("main".to_string()));
+
+        let manifest = build_manifest(vec![(repo, git_ref)], serde_json::json!({ "max_bytes": 10 })).unwrap();
+        let json = serde_json::to_value(&manifest).unwrap();
+        let demo = &json["repositories"][0];
+        assert_eq!(demo["ref"], serde_json::json!({ "kind": "head", "name": "main" }));
+        assert_eq!(demo["files"][0]["language"], "rust");
+        assert_eq!(demo["files"][0]["lines"], 1);
+        // Same id as `git hash-object`
+        assert_eq!(demo["files"][0]["content_hash"], "f328e4d9d04c31d0d70d16d21a07d1613be9d577");
+        assert_eq!(demo["files"][2]["content_hash"], serde_json::Value::Null);
+        assert_eq!(json["stats"]["rendered_files"], 2);
+        assert_eq!(json["stats"]["rendered_lines"], 3);
+        assert_eq!(json["stats"]["skipped_by_reason"], serde_json::json!({ "binary": 1 }));
+        assert_eq!(json["stats"]["total_files"], 3);
+        assert_eq!(json["stats"]["deleted_files"], 1);
+        assert_eq!(json["stats"]["languages"]["rust"]["files"], 2);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::file_analyzer::{FileCounts, FileInfo};
+use crate::html_builder::{is_markdown, RepoSection};
+use crate::language;
+use crate::tree_gen;
+use crate::utils::{bytes_human, slugify};
+use anyhow::Result;
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+use syntect::parsing::SyntaxSet;
+
+/// Generate a Markdown document: metadata, the annotated directory tree, a
+/// linked table of contents and every rendered file, code in fenced blocks
+/// tagged with its language and Markdown files inlined with their headings
+/// demoted. Several repositories each get their own section.
+pub fn generate_markdown(repos: &[RepoSection]) -> Result<String> {
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+    let mut out = Vec::new();
+
+    match repos {
+        [repo] => {
+            out.push(format!("# Flattened repo – {}", repo.repo_url));
+            out.push(String::new());
+            push_repo(&mut out, repo, 2, "file-", &syntax_set)?;
+        }
+        _ => {
+            let names: Vec<_> = repos.iter().map(|r| r.repo_name).collect();
+            out.push(format!("# Flattened repos – {}", names.join(", ")));
+            out.push(String::new());
+            for repo in repos {
+                let anchor = format!("repo-{}", slugify(repo.repo_name));
+                out.push(format!("<a id=\"{}\"></a>", anchor));
+                out.push(String::new());
+                out.push(format!("## {}", escape(repo.repo_name)));
+                out.push(String::new());
+                push_repo(&mut out, repo, 3, &format!("{}-file-", anchor), &syntax_set)?;
+            }
+        }
+    }
+
+    Ok(out.join("\n"))
+}
+
+/// Append the sections of one repository with headings at `level`. File
+/// anchors match the HTML page: `anchor_prefix` and the slugified path.
+fn push_repo(
+    out: &mut Vec<String>,
+    repo: &RepoSection,
+    level: usize,
+    anchor_prefix: &str,
+    syntax_set: &SyntaxSet,
+) -> Result<()> {
+    let heading = "#".repeat(level);
+    let rendered: Vec<&FileInfo> = repo.file_infos.iter().filter(|f| f.decision.include).collect();
+
+    out.push(format!("- **Repository:** {}", repo.repo_url));
+    out.push(format!("- **HEAD commit:** {}", repo.head_commit));
+    let counts = FileCounts::of(repo.file_infos);
+    let deleted = if counts.deleted > 0 {
+        format!(" · **Deleted:** {}", counts.deleted)
+    } else {
+        String::new()
+    };
+    out.push(format!(
+        "- **Total files:** {} · **Rendered:** {} · **Skipped:** {}{}",
+        counts.total, counts.rendered, counts.skipped, deleted
+    ));
+    out.push(String::new());
+
+    out.push(format!("{} Directory tree", heading));
+    out.push(String::new());
+    let tree = tree_gen::generate_tree_from_files(repo.repo_name, repo.file_infos, repo.submodules);
+    out.push(fenced(&tree, "text"));
+    out.push(String::new());
+
+    out.push(format!("{} Table of contents ({})", heading, rendered.len()));
+    out.push(String::new());
+    for info in &rendered {
+        out.push(format!(
+            "- [{}](#{}{}) ({})",
+            escape(&info.rel),
+            anchor_prefix,
+            slugify(&info.rel),
+            bytes_human(info.size)
+        ));
+    }
+    out.push(String::new());
+
+    for info in &rendered {
+        let content = info.read_to_string()?;
+        out.push(format!("<a id=\"{}{}\"></a>", anchor_prefix, slugify(&info.rel)));
+        out.push(String::new());
+        out.push(format!("{} {}", heading, escape(&info.rel)));
+        out.push(String::new());
+        if is_markdown(&info.rel) {
+            out.push(demote_headings(&content, level).trim_end().to_string());
+        } else {
+            let tag = language::detect(syntax_set, &info.rel, &content).unwrap_or_default();
+            out.push(fenced(&content, &tag));
+        }
+        out.push(String::new());
+    }
+    Ok(())
+}
+
+/// Backslash-escape Markdown punctuation so a path like `src/__init__.py`
+/// stays literal in headings and link text
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '!' | '|' | '~') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Wrap text in a code fence longer than any backtick run inside it
+fn fenced(content: &str, tag: &str) -> String {
+    let longest_run = content
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat((longest_run + 1).max(3));
+    format!("{}{}\n{}\n{}", fence, tag, content.trim_end_matches('\n'), fence)
+}
+
+/// Push every heading of a Markdown document `by` levels down (capped at
+/// level 6) so it nests under the file's own heading. Setext headings are
+/// rewritten as ATX, their lines joined into one; code blocks are left alone.
+fn demote_headings(content: &str, by: usize) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut copied = 0;
+    let mut heading: Option<(usize, std::ops::Range<usize>)> = None;
+    let mut inner: Option<std::ops::Range<usize>> = None;
+
+    for (event, range) in Parser::new(content).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                heading = Some((heading_depth(level), range));
+                inner = None;
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                let Some((depth, range)) = heading.take() else { continue };
+                let text = inner
+                    .take()
+                    .map(|r| content[r].lines().map(str::trim).collect::<Vec<_>>().join(" "))
+                    .unwrap_or_default();
+                result.push_str(&content[copied..range.start]);
+                result.push_str(&format!("{} {}", "#".repeat((depth + by).min(6)), text));
+                // Keep the line break that ended the heading
+                if content[range.clone()].ends_with('\n') {
+                    result.push('\n');
+                }
+                copied = range.end;
+            }
+            _ if heading.is_some() => {
+                inner = Some(match inner.take() {
+                    Some(r) => r.start.min(range.start)..r.end.max(range.end),
+                    None => range,
+                });
+            }
+            _ => {}
+        }
+    }
+    result.push_str(&content[copied..]);
+    result
+}
+
+fn heading_depth(level: HeadingLevel) -> usize {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_analyzer::text_file;
+
+    #[test]
+    fn test_demote_headings() {
+        let doc = "# Title\n\nText with # hash\n\nSub\n---\n\n```sh\n# comment\n```\n##### Deep #####\n";
+        assert_eq!(
+            demote_headings(doc, 2),
+            "### Title\n\nText with # hash\n\n#### Sub\n\n```sh\n# comment\n```\n###### Deep\n"
+        );
+        assert_eq!(demote_headings("Two\nlines\n===\n", 1), "## Two lines\n");
+    }
+
+    #[test]
+    fn test_generate_markdown() {
+        let files = vec![
+            text_file("README.md", "# Demo\nHello", "ok"),
+            text_file("src/main.rs", "fn main() {\n    // ```\n}\n", "ok"),
+            text_file("src/__init__.py", "", "ok"),
+        ];
+        let repo = RepoSection {
+            repo_url: "https://example.com/demo",
+            repo_name: "demo",
+            head_commit: "abc123",
+            file_infos: &files,
+            submodules: &[],
+        };
+
+        let md = generate_markdown(&[repo]).unwrap();
+        assert!(md.starts_with("# Flattened repo – https://example.com/demo\n"));
+        assert!(md.contains("- [src/main.rs](#file-src-main-rs) (25 B)"));
+        assert!(md.contains("- [src/\\_\\_init\\_\\_.py](#file-src-__init__-py) (0 B)"));
+        assert!(md.contains("\n## src/\\_\\_init\\_\\_.py\n"));
+        assert!(md.contains("<a id=\"file-README-md\"></a>\n\n## README.md\n\n### Demo\nHello\n"));
+        assert!(md.contains("## src/main.rs\n\n````rust\nfn main() {\n    // ```\n}\n````"));
+        assert!(md.contains("```text\ndemo\n├── src\n│   ├── __init__.py  (0 B, rendered)\n│   └── main.rs  (25 B, rendered)\n└── README.md  (12 B, rendered)\n```"));
+    }
+}
@@ -0,0 +1,399 @@
+use crate::cxml_gen;
+use crate::file_analyzer::{FileCounts, FileInfo};
+use crate::html_builder::{self, RepoSection};
+use crate::tokens::{self, TokenOptions};
+use crate::utils::{bytes_human, html_escape, slugify, url_escape_path};
+use anyhow::{Context, Result};
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+
+/// A file of a static site, with its path relative to the site root
+pub struct SiteFile {
+    pub path: String,
+    pub content: String,
+}
+
+/// Build a multi-page static site: `index.html` with the stats, directory
+/// tree and skip lists, one page per rendered file under `files/`, the CXML
+/// document as `llm.txt`, and shared CSS and JS under `assets/`. The sidebar
+/// lives in `assets/nav.js` so each page stays small. All links are
+/// relative, so the site works from `file://` and any static host.
+pub fn build_site(repos: &[RepoSection], tokens: &TokenOptions) -> Result<Vec<SiteFile>> {
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+    let theme_set = ThemeSet::load_defaults();
+    let theme = &theme_set.themes["InspiredGitHub"];
+
+    let combined = repos.len() > 1;
+    let rendered_count: usize = repos
+        .iter()
+        .map(|r| r.file_infos.iter().filter(|f| f.decision.include).count())
+        .sum();
+    let mut files = Vec::new();
+
+    // File pages, each linking to its neighbours
+    for repo in repos {
+        let rendered: Vec<_> = repo.file_infos.iter().filter(|f| f.decision.include).collect();
+        for (i, info) in rendered.iter().enumerate() {
+            let path = page_path(repo, combined, info);
+            let root = root_prefix(&path);
+            let link = |other: Option<&&FileInfo>, label: &str| match other {
+                Some(other) => format!(
+                    "<a href=\"{}{}\">{}</a>",
+                    root,
+                    url_escape_path(&page_path(repo, combined, other)),
+                    label
+                ),
+                None => format!("<span class=\"muted\">{}</span>", label),
+            };
+            let pager = format!(
+                "<nav class=\"pager\">{} · <a href=\"{}index.html\">Index</a> · {}</nav>",
+                link(i.checked_sub(1).and_then(|p| rendered.get(p)), "← Previous"),
+                root,
+                link(rendered.get(i + 1), "Next →")
+            );
+            let content = info.read_to_string()?;
+            let body = html_builder::render_file_body(&info.rel, &content, &syntax_set, theme)
+                .with_context(|| format!("Failed to render {}", info.rel))?;
+
+            let main = format!(
+                r##"
+    <nav class="breadcrumb"><a href="{root}{repo_link}">{repo_name}</a> / {rel}</nav>
+    {pager}
+    <section class="file-section">
+      <h2>{rel} <span class="muted">({size} · {tokens} tokens)</span></h2>
+      <div class="file-body">{body}</div>
+    </section>
+    {pager}
+"##,
+                root = root,
+                repo_link = repo_link(repo, combined),
+                repo_name = html_escape(repo.repo_name),
+                rel = html_escape(&info.rel),
+                size = bytes_human(info.size),
+                tokens = tokens::tokens_human(tokens.tokenizer.count(&content)),
+                pager = pager,
+                body = body
+            );
+            let title = format!("{} – {}", info.rel, repo.repo_name);
+            files.push(SiteFile {
+                content: page(&title, &path, rendered_count, &main),
+                path,
+            });
+        }
+    }
+
+    files.push(SiteFile {
+        path: "index.html".to_string(),
+        content: build_index(repos, rendered_count),
+    });
+
+    let cxml_text = if combined {
+        let cxml_repos: Vec<_> = repos.iter().map(|r| (r.repo_name, r.file_infos)).collect();
+        cxml_gen::generate_combined_cxml(&cxml_repos, tokens)?
+    } else {
+        cxml_gen::generate_cxml(repos[0].file_infos, tokens)?
+    };
+    files.push(SiteFile { path: "llm.txt".to_string(), content: cxml_text });
+
+    files.push(SiteFile {
+        path: "assets/style.css".to_string(),
+        content: format!("{}{}", html_builder::generate_css(), SITE_CSS),
+    });
+    files.push(SiteFile {
+        path: "assets/script.js".to_string(),
+        content: html_builder::get_javascript().to_string(),
+    });
+    files.push(SiteFile {
+        path: "assets/nav.js".to_string(),
+        content: NAV_JS.replace("SIDEBAR_HTML", &serde_json::to_string(&sidebar_html(repos))?),
+    });
+    Ok(files)
+}
+
+/// Path of a file's page: `files/<rel>.html`, with the repository name
+/// after `files/` when several are combined
+fn page_path(repo: &RepoSection, combined: bool, info: &FileInfo) -> String {
+    let mut parts = vec!["files".to_string()];
+    if combined {
+        parts.push(dir_name(repo.repo_name));
+    }
+    let (dirs, name) = info.rel.rsplit_once('/').unwrap_or(("", &info.rel));
+    parts.extend(dirs.split('/').filter(|d| !d.is_empty()).map(dir_name));
+    parts.push(format!("{}.html", name));
+    parts.join("/")
+}
+
+/// Name of a directory on the way to a page. Pages end in `.html`, so a
+/// directory `foo.html/` would collide with the page of a file `foo`; such
+/// directories, and those already ending in `.html_`, get one more `_`.
+fn dir_name(name: &str) -> String {
+    if name.trim_end_matches('_').ends_with(".html") {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Link from the site root to a repository's part of the index
+fn repo_link(repo: &RepoSection, combined: bool) -> String {
+    if combined {
+        format!("index.html#repo-{}", slugify(repo.repo_name))
+    } else {
+        "index.html".to_string()
+    }
+}
+
+/// `../` for every directory between a page and the site root
+fn root_prefix(path: &str) -> String {
+    "../".repeat(path.matches('/').count())
+}
+
+/// The sidebar folder tree(s), with links relative to the site root
+fn sidebar_html(repos: &[RepoSection]) -> String {
+    let combined = repos.len() > 1;
+    let tree = |repo: &RepoSection| {
+        let link = |info: &FileInfo| html_escape(&url_escape_path(&page_path(repo, combined, info)));
+        html_builder::render_sidebar_tree(repo, &link, &|info| info.size_human())
+    };
+    if !combined {
+        return tree(&repos[0]);
+    }
+
+    let mut groups = Vec::new();
+    for repo in repos {
+        let rendered: Vec<_> = repo.file_infos.iter().filter(|f| f.decision.include).collect();
+        groups.push(format!(
+            "<li class=\"toc-repo\"><details open><summary><a href=\"{link}\"><strong>{name}</strong></a> <span class=\"muted\">({count} · {size})</span></summary>\n<ul class=\"toc\">\n{items}\n</ul></details></li>",
+            link = repo_link(repo, combined),
+            name = html_escape(repo.repo_name),
+            count = rendered.len(),
+            size = bytes_human(rendered.iter().map(|f| f.size).sum()),
+            items = tree(repo)
+        ));
+    }
+    groups.join("\n")
+}
+
+/// Build the index page: stats, directory tree and skip lists of every
+/// repository, and a link to the LLM view
+fn build_index(repos: &[RepoSection], rendered_count: usize) -> String {
+    let combined = repos.len() > 1;
+    let mut counts = FileCounts::default();
+    for repo in repos {
+        counts.add(FileCounts::of(repo.file_infos));
+    }
+
+    let mut sections = Vec::new();
+    for repo in repos {
+        let link = |info: &FileInfo| html_escape(&url_escape_path(&page_path(repo, combined, info)));
+        let heading = if combined {
+            format!("<h1>{}</h1>\n", html_escape(repo.repo_name))
+        } else {
+            String::new()
+        };
+        sections.push(format!(
+            r##"
+<section class="repo-section" id="repo-{anchor}">
+  {heading}<div class="meta">
+    <div><strong>Repository:</strong> <a href="{url}">{url}</a></div>
+    <small><strong>HEAD commit:</strong> {head_commit}</small>
+    <div class="counts">
+      {counts}
+    </div>
+  </div>
+
+  <section>
+    <h2>Directory tree</h2>
+    <pre class="tree">{tree_html}</pre>
+  </section>
+
+  <section>
+    <h2>Skipped items</h2>
+    {skipped_html}
+  </section>
+{deleted_html}</section>
+"##,
+            anchor = slugify(repo.repo_name),
+            heading = heading,
+            url = html_escape(repo.repo_url),
+            head_commit = html_escape(repo.head_commit),
+            counts = html_builder::counts_html(&FileCounts::of(repo.file_infos)),
+            tree_html = html_builder::render_tree(repo, &link),
+            skipped_html = html_builder::render_skipped(repo.file_infos),
+            deleted_html = html_builder::render_deleted(repo.file_infos)
+        ));
+    }
+
+    let summary = if combined {
+        let repo_links: Vec<_> = repos
+            .iter()
+            .map(|r| format!("<a href=\"#repo-{}\">{}</a>", slugify(r.repo_name), html_escape(r.repo_name)))
+            .collect();
+        format!(
+            "<div><strong>Repositories ({}):</strong> {}</div>\n        <div class=\"counts\">{}</div>",
+            repos.len(),
+            repo_links.join(" · "),
+            html_builder::counts_html(&counts)
+        )
+    } else {
+        String::new()
+    };
+
+    let main = format!(
+        r##"
+    <section>
+        <div class="meta">
+        {summary}
+        <div>🤖 <strong>LLM view:</strong> <a href="llm.txt">llm.txt</a> <span class="muted">(CXML of all rendered files)</span></div>
+        </div>
+    </section>
+
+    {sections}
+"##,
+        summary = summary,
+        sections = sections.join("\n")
+    );
+    let names: Vec<_> = repos.iter().map(|r| r.repo_name).collect();
+    page(&format!("Flattened repo – {}", names.join(", ")), "index.html", rendered_count, &main)
+}
+
+/// Wrap the main content of a page at `path` in the shared layout
+fn page(title: &str, path: &str, rendered_count: usize, main: &str) -> String {
+    let root = root_prefix(path);
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8" />
+<meta name="viewport" content="width=device-width, initial-scale=1" />
+<title>{title}</title>
+<link rel="stylesheet" href="{root}assets/style.css" />
+</head>
+<body>
+<a id="top"></a>
+
+<div class="page">
+  <nav id="sidebar"><div class="sidebar-inner">
+      <h2>Contents ({rendered_count})</h2>
+      <div class="tree-controls">
+        <button onclick="setFolders(true)">Expand all</button>
+        <button onclick="setFolders(false)">Collapse all</button>
+      </div>
+      <ul class="toc toc-sidebar">
+        <li><a href="{root}index.html">↑ Index</a></li>
+      </ul>
+      <ul class="toc toc-sidebar" id="site-nav" data-root="{root}" data-page="{page}"></ul>
+  </div></nav>
+
+  <main class="container">
+{main}
+  </main>
+</div>
+
+<script src="{root}assets/nav.js"></script>
+<script src="{root}assets/script.js"></script>
+</body>
+</html>
+"##,
+        title = html_escape(title),
+        root = root,
+        rendered_count = rendered_count,
+        page = html_escape(&url_escape_path(path)),
+        main = main,
+    )
+}
+
+/// Extra CSS for site pages
+const SITE_CSS: &str = r#"
+  .breadcrumb { margin: 0.5rem 0; color: #57606a; }
+  .breadcrumb a { color: #0366d6; text-decoration: none; }
+  .pager { margin: 0.5rem 0; font-size: 0.9rem; }
+  .pager a { color: #0366d6; text-decoration: none; }
+"#;
+
+/// Fills the sidebar of every page with the shared folder tree, makes its
+/// root-relative links relative to the page, and highlights the current file
+const NAV_JS: &str = r#"
+(function () {
+  const nav = document.getElementById('site-nav');
+  if (!nav) return;
+  nav.innerHTML = SIDEBAR_HTML;
+  const root = nav.dataset.root || '';
+  const page = nav.dataset.page;
+  let current = null;
+
+  nav.querySelectorAll('a[href]').forEach(a => {
+    const href = a.getAttribute('href');
+    if (href === page) current = a;
+    a.setAttribute('href', root + href);
+  });
+  if (!current) return;
+
+  current.classList.add('current');
+  for (let el = current.parentElement; el && el !== nav; el = el.parentElement) {
+    if (el.tagName === 'DETAILS') el.open = true;
+  }
+  const sidebar = document.getElementById('sidebar');
+  const r = current.getBoundingClientRect();
+  const s = sidebar.getBoundingClientRect();
+  if (r.top < s.top || r.bottom > s.bottom) {
+    sidebar.scrollTop += r.top - s.top - s.height / 2;
+  }
+})();
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_analyzer::{file, text_file};
+
+    #[test]
+    fn test_build_site() {
+        let files = vec![
+            text_file("README.md", "# Demo\n", "ok"),
+            text_file("src/a b.rs", "fn main() {}\n", "ok"),
+        ];
+        let repo = RepoSection {
+            repo_url: "https://example.com/demo",
+            repo_name: "demo",
+            head_commit: "abc123",
+            file_infos: &files,
+            submodules: &[],
+        };
+
+        let site = build_site(&[repo], &TokenOptions::default()).unwrap();
+        let get = |path: &str| &site.iter().find(|f| f.path == path).unwrap().content;
+        let paths: Vec<_> = site.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec!["files/README.md.html", "files/src/a b.rs.html", "index.html", "llm.txt", "assets/style.css", "assets/script.js", "assets/nav.js"]
+        );
+
+        let page = get("files/src/a b.rs.html");
+        assert!(page.contains("href=\"../../assets/style.css\""));
+        assert!(page.contains("data-root=\"../../\" data-page=\"files/src/a%20b.rs.html\""));
+        assert!(page.contains("<a href=\"../../files/README.md.html\">← Previous</a>"));
+        assert!(get("index.html").contains("<a href=\"files/src/a%20b.rs.html\">a b.rs</a>"));
+        assert!(get("assets/nav.js").contains("href=\\\"files/src/a%20b.rs.html\\\""));
+        assert!(get("llm.txt").contains("<source>src/a b.rs</source>"));
+    }
+
+    #[test]
+    fn test_page_paths_do_not_collide() {
+        let files = ["foo", "foo.html/bar", "foo.html_/baz", "foo.html"].map(|rel| file(rel, 0, "ok"));
+        let repo = RepoSection {
+            repo_url: "https://example.com/demo",
+            repo_name: "demo",
+            head_commit: "abc123",
+            file_infos: &files,
+            submodules: &[],
+        };
+
+        let paths: Vec<_> = files.iter().map(|f| page_path(&repo, false, f)).collect();
+        assert_eq!(
+            paths,
+            vec!["files/foo.html", "files/foo.html_/bar.html", "files/foo.html__/baz.html", "files/foo.html.html"]
+        );
+        assert_eq!(page_path(&repo, true, &files[1]), "files/demo/foo.html_/bar.html");
+    }
+}
@@ -0,0 +1,109 @@
+use crate::archive;
+use std::path::{Path, PathBuf};
+
+/// Where a repository to render comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepoSource {
+    Local(PathBuf),
+    Remote(String),
+    /// A .tar, .tar.gz/.tgz or .zip file, unpacked before rendering
+    Archive(PathBuf),
+}
+
+/// Host shorthands, e.g. `gitlab:group/project`
+const HOST_PREFIXES: &[(&str, &str)] = &[
+    ("github:", "https://github.com/"),
+    ("gitlab:", "https://gitlab.com/"),
+    ("codeberg:", "https://codeberg.org/"),
+];
+
+/// URL schemes handed to git as-is
+const REMOTE_SCHEMES: &[&str] = &["http://", "https://", "ssh://", "git://"];
+
+/// Work out what the command-line input refers to. Existing directories and
+/// archives win, then URLs and host shorthands; a bare `owner/repo` means GitHub
+/// unless `owner` exists locally, so a typo like `src/app` stays a local path.
+pub fn detect_source(input: &str) -> RepoSource {
+    let path = Path::new(input);
+
+    // Check if it's a local path that exists
+    if path.is_dir() {
+        return RepoSource::Local(path.to_path_buf());
+    }
+    if path.is_file() && archive::archive_kind(path).is_some() {
+        return RepoSource::Archive(path.to_path_buf());
+    }
+
+    if let Some(rest) = input.strip_prefix("file://") {
+        return RepoSource::Local(PathBuf::from(rest));
+    }
+    if REMOTE_SCHEMES.iter().any(|scheme| input.starts_with(scheme)) || is_scp_style(input) {
+        return RepoSource::Remote(input.to_string());
+    }
+    for (prefix, base) in HOST_PREFIXES {
+        if let Some(rest) = input.strip_prefix(prefix) {
+            return RepoSource::Remote(format!("{}{}", base, rest.trim_matches('/')));
+        }
+    }
+    if is_github_shorthand(input) {
+        return RepoSource::Remote(format!("https://github.com/{}", input));
+    }
+
+    // Try as local path, even if it doesn't exist yet
+    RepoSource::Local(path.to_path_buf())
+}
+
+/// scp-like SSH syntax, `user@host:path`
+fn is_scp_style(input: &str) -> bool {
+    match input.split_once(':') {
+        Some((user_host, path)) => {
+            user_host.contains('@') && !user_host.contains('/') && !path.is_empty()
+        }
+        None => false,
+    }
+}
+
+/// `owner/repo`: exactly two plain segments, neither starting with a dot,
+/// and no local file or directory named `owner`
+fn is_github_shorthand(input: &str) -> bool {
+    let parts: Vec<&str> = input.split('/').collect();
+    parts.len() == 2
+        && !Path::new(parts[0]).exists()
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && !part.starts_with('.')
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(url: &str) -> RepoSource {
+        RepoSource::Remote(url.to_string())
+    }
+
+    #[test]
+    fn test_detect_source_shorthands() {
+        assert_eq!(detect_source("rust-lang/regex"), remote("https://github.com/rust-lang/regex"));
+        assert_eq!(detect_source("github:owner/repo"), remote("https://github.com/owner/repo"));
+        assert_eq!(
+            detect_source("gitlab:group/sub/project"),
+            remote("https://gitlab.com/group/sub/project")
+        );
+        assert_eq!(detect_source("codeberg:owner/repo"), remote("https://codeberg.org/owner/repo"));
+    }
+
+    #[test]
+    fn test_detect_source_urls_and_paths() {
+        assert_eq!(detect_source("ssh://git@host:2222/repo.git"), remote("ssh://git@host:2222/repo.git"));
+        assert_eq!(detect_source("git@github.com:owner/repo.git"), remote("git@github.com:owner/repo.git"));
+        assert_eq!(detect_source("file:///srv/git/repo"), RepoSource::Local(PathBuf::from("/srv/git/repo")));
+        assert_eq!(detect_source("."), RepoSource::Local(PathBuf::from(".")));
+        assert_eq!(detect_source("./missing/dir"), RepoSource::Local(PathBuf::from("./missing/dir")));
+        assert_eq!(detect_source("a/b/c"), RepoSource::Local(PathBuf::from("a/b/c")));
+        // `src` exists in the crate root, so this is a missing local path, not GitHub
+        assert_eq!(detect_source("src/missing"), RepoSource::Local(PathBuf::from("src/missing")));
+    }
+}
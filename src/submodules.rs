@@ -0,0 +1,433 @@
+use crate::file_analyzer::{self, FileInfo, ScanOptions};
+use crate::git_ops::{self, CloneOptions, GitRef};
+use crate::utils::{short_id, slugify};
+use anyhow::{Context, Result};
+use git2::{Config, ObjectType, Oid, Repository, Tree, TreeWalkMode, TreeWalkResult};
+use std::io::Write;
+use std::path::Path;
+
+/// A submodule rendered at its pinned commit
+#[derive(Debug, Clone)]
+pub struct SubmoduleInfo {
+    pub path: String,   // path relative to the top-level repo root (slash-separated)
+    pub url: String,
+    pub commit: String,
+}
+
+impl SubmoduleInfo {
+    /// Label used in the TOC and directory tree, e.g. `📦 submodule: url @ abc12345`
+    pub fn label(&self) -> String {
+        format!("📦 submodule: {} @ {}", self.url, short_id(&self.commit))
+    }
+}
+
+/// Check whether a repo-relative path lies inside the `--subdir` scope
+fn in_scope(path: &str, subdir: Option<&str>) -> bool {
+    match subdir {
+        // Inside the subdir, or a parent of it
+        Some(dir) => {
+            path == dir
+                || path.starts_with(&format!("{}/", dir))
+                || dir.starts_with(&format!("{}/", path))
+        }
+        None => true,
+    }
+}
+
+/// Check whether `subdir` lies inside one of `submodules`, so its files come
+/// from the submodule rather than the superproject's own scan
+pub fn contains_subdir(submodules: &[SubmoduleInfo], subdir: Option<&str>) -> bool {
+    subdir.is_some_and(|dir| {
+        submodules
+            .iter()
+            .any(|submodule| dir.starts_with(&format!("{}/", submodule.path)))
+    })
+}
+
+/// Find the submodules of a working tree, recursively, without changing it.
+/// Checked-out submodules are rendered from disk by the working tree scan;
+/// the others are read at their pinned commits from git objects, like
+/// `collect_tree_submodules` does, and their files are returned.
+pub fn collect_worktree_submodules(
+    repo_dir: &Path,
+    prefix: &str,
+    options: &ScanOptions,
+    clone_options: &CloneOptions,
+    work_dir: &Path,
+    show_progress: bool,
+) -> Result<(Vec<SubmoduleInfo>, Vec<FileInfo>)> {
+    let repo = Repository::open(repo_dir)
+        .with_context(|| format!("{} is not a git repository", repo_dir.display()))?;
+    let mut submodules = Vec::new();
+    let mut files = Vec::new();
+
+    for submodule in repo.submodules().context("Failed to read .gitmodules")? {
+        let path = submodule.path().to_string_lossy().replace('\\', "/");
+        let rel = format!("{}{}", prefix, path);
+        if !in_scope(&rel, options.subdir.as_deref()) {
+            continue;
+        }
+
+        let url = resolve_url(&repo, submodule.url().unwrap_or("(unknown)"));
+        let Some(pinned) = submodule.head_id().or_else(|| submodule.index_id()) else {
+            continue;
+        };
+
+        // Checked out: keep whatever is on disk
+        if submodule.open().is_ok_and(|sub_repo| sub_repo.workdir().is_some()) {
+            let commit = submodule.workdir_id().unwrap_or(pinned);
+            eprintln!("📦 Using checked-out submodule {} ({})", rel, url);
+            submodules.push(SubmoduleInfo {
+                path: rel.clone(),
+                url,
+                commit: commit.to_string(),
+            });
+            let (nested, nested_files) = collect_worktree_submodules(
+                &repo_dir.join(&path),
+                &format!("{}/", rel),
+                options,
+                clone_options,
+                work_dir,
+                show_progress,
+            )?;
+            submodules.extend(nested);
+            files.extend(nested_files);
+            continue;
+        }
+
+        let name = submodule.name().unwrap_or(&path);
+        collect_pinned(
+            &repo,
+            name,
+            url,
+            rel,
+            pinned,
+            options,
+            clone_options,
+            work_dir,
+            show_progress,
+            &mut submodules,
+            &mut files,
+        )?;
+    }
+
+    Ok((submodules, files))
+}
+
+/// Collect the files of every submodule pinned in `tree`, recursively, reading
+/// them from git objects. Submodule repositories come from `.git/modules` when
+/// available and are otherwise cloned (bare) into `work_dir`.
+pub fn collect_tree_submodules(
+    repo: &Repository,
+    tree: &Tree,
+    prefix: &str,
+    options: &ScanOptions,
+    clone_options: &CloneOptions,
+    work_dir: &Path,
+    show_progress: bool,
+) -> Result<(Vec<SubmoduleInfo>, Vec<FileInfo>)> {
+    let mut submodules = Vec::new();
+    let mut files = Vec::new();
+
+    // Gitlinks are tree entries that point at a commit
+    let mut gitlinks: Vec<(String, Oid)> = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() == Some(ObjectType::Commit) {
+            let name = String::from_utf8_lossy(entry.name_bytes());
+            gitlinks.push((format!("{}{}", root, name), entry.id()));
+        }
+        TreeWalkResult::Ok
+    })
+    .context("Failed to walk tree")?;
+    if gitlinks.is_empty() {
+        return Ok((submodules, files));
+    }
+
+    let modules = read_gitmodules(repo, tree)?;
+    for (path, oid) in gitlinks {
+        let rel = format!("{}{}", prefix, path);
+        if !in_scope(&rel, options.subdir.as_deref()) {
+            continue;
+        }
+
+        let Some((name, _, url)) = modules.iter().find(|(_, p, _)| *p == path) else {
+            eprintln!("⚠️  Submodule {} is missing from .gitmodules, skipping", rel);
+            continue;
+        };
+        let url = resolve_url(repo, url);
+        collect_pinned(
+            repo,
+            name,
+            url,
+            rel,
+            oid,
+            options,
+            clone_options,
+            work_dir,
+            show_progress,
+            &mut submodules,
+            &mut files,
+        )?;
+    }
+
+    Ok((submodules, files))
+}
+
+/// Read one submodule at its pinned commit `oid` from git objects, then its
+/// own submodules. The repository comes from the superproject's
+/// `.git/modules` when available and is otherwise cloned (bare) into
+/// `work_dir`; a submodule that cannot be fetched is reported and skipped.
+#[allow(clippy::too_many_arguments)]
+fn collect_pinned(
+    repo: &Repository,
+    name: &str,
+    url: String,
+    rel: String,
+    oid: Oid,
+    options: &ScanOptions,
+    clone_options: &CloneOptions,
+    work_dir: &Path,
+    show_progress: bool,
+    submodules: &mut Vec<SubmoduleInfo>,
+    files: &mut Vec<FileInfo>,
+) -> Result<()> {
+    eprintln!("📦 Reading submodule {} ({})", rel, url);
+    let sub_repo = match open_module(repo, name, oid) {
+        Some(sub_repo) => sub_repo,
+        None => {
+            let dest = work_dir.join(slugify(&rel));
+            let pinned = GitRef::Commit(oid.to_string());
+            let submodule_clone_options = CloneOptions {
+                parent_url: origin_url(repo),
+                ..clone_options.clone()
+            };
+            let cloned = git_ops::clone_bare(&url, &dest, &pinned, &submodule_clone_options)
+                .and_then(|_| git_ops::open_repo(&dest));
+            match cloned {
+                Ok(sub_repo) => sub_repo,
+                Err(e) => {
+                    eprintln!("⚠️  Failed to fetch submodule {}: {:#}", rel, e);
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    let Ok(sub_tree) = sub_repo.find_commit(oid).and_then(|c| c.tree()) else {
+        eprintln!("⚠️  Submodule {} does not contain commit {}", rel, oid);
+        return Ok(());
+    };
+
+    // A `--subdir` inside the submodule narrows it to that directory
+    let sub_prefix = format!("{}/", rel);
+    let scope = options
+        .subdir
+        .as_deref()
+        .and_then(|dir| dir.strip_prefix(&sub_prefix))
+        .unwrap_or("");
+    let nested_options = ScanOptions {
+        subdir: None,
+        ..options.clone()
+    };
+    files.extend(file_analyzer::collect_subtree(
+        &sub_repo,
+        &sub_tree,
+        scope,
+        &sub_prefix,
+        &nested_options,
+        show_progress,
+    )?);
+    submodules.push(SubmoduleInfo {
+        path: rel,
+        url,
+        commit: oid.to_string(),
+    });
+
+    let (nested, nested_files) = collect_tree_submodules(
+        &sub_repo,
+        &sub_tree,
+        &sub_prefix,
+        options,
+        clone_options,
+        work_dir,
+        show_progress,
+    )?;
+    submodules.extend(nested);
+    files.extend(nested_files);
+    Ok(())
+}
+
+/// Parse `.gitmodules` from a tree into (name, path, url) triples
+fn read_gitmodules(repo: &Repository, tree: &Tree) -> Result<Vec<(String, String, String)>> {
+    let Ok(entry) = tree.get_path(Path::new(".gitmodules")) else {
+        return Ok(Vec::new());
+    };
+    let blob = repo.find_blob(entry.id()).context("Failed to read .gitmodules")?;
+
+    // git2 only parses config from files
+    let mut file = tempfile::NamedTempFile::new()?;
+    file.write_all(blob.content())?;
+    let config = Config::open(file.path()).context("Failed to parse .gitmodules")?;
+
+    let mut modules = Vec::new();
+    let mut entries = config.entries(Some(r"submodule\..*\.path"))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        let (Some(key), Some(path)) = (entry.name(), entry.value()) else {
+            continue;
+        };
+        let name = &key["submodule.".len()..key.len() - ".path".len()];
+        if let Ok(url) = config.get_string(&format!("submodule.{}.url", name)) {
+            modules.push((name.to_string(), path.to_string(), url));
+        }
+    }
+    Ok(modules)
+}
+
+/// Resolve a relative submodule URL (`../lib.git`) against the superproject's
+/// origin, or its location on disk when it has no remote
+fn resolve_url(repo: &Repository, url: &str) -> String {
+    if !url.starts_with("./") && !url.starts_with("../") {
+        return url.to_string();
+    }
+
+    let base = origin_url(repo).unwrap_or_else(|| {
+        let dir = repo.workdir().unwrap_or_else(|| repo.path());
+        dir.to_string_lossy().trim_end_matches('/').to_string()
+    });
+
+    let mut base: Vec<&str> = base.trim_end_matches('/').split('/').collect();
+    for part in url.split('/') {
+        match part {
+            "." | "" => {}
+            ".." => {
+                base.pop();
+            }
+            other => base.push(other),
+        }
+    }
+    base.join("/")
+}
+
+/// URL of the `origin` remote of `repo`, if it has one
+fn origin_url(repo: &Repository) -> Option<String> {
+    repo.find_remote("origin").ok()?.url().map(str::to_string)
+}
+
+/// Open a submodule's repository from the superproject's `.git/modules` if it
+/// already has the pinned commit
+fn open_module(repo: &Repository, name: &str, oid: Oid) -> Option<Repository> {
+    let module = Repository::open(repo.path().join("modules").join(name)).ok()?;
+    let has_commit = module.find_commit(oid).is_ok();
+    has_commit.then_some(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// A superproject with `vendor/lib` pinned to the first commit of `lib`
+    fn superproject(root: &Path) -> (Repository, Oid) {
+        let lib_dir = root.join("lib");
+        let lib = git_ops::tests::init_repo_with_commits(&lib_dir, &[("lib.rs", "v1"), ("inner/mod.rs", "inner")]);
+        let pinned = lib.head().unwrap().peel_to_commit().unwrap().id();
+        git_ops::tests::init_repo_with_commits(&lib_dir, &[("lib.rs", "v2")]);
+
+        let main_dir = root.join("main");
+        let repo = git_ops::tests::init_repo_with_commits(&main_dir, &[("main.rs", "main")]);
+        let mut submodule = repo
+            .submodule(lib_dir.to_str().unwrap(), Path::new("vendor/lib"), true)
+            .unwrap();
+        let sub_repo = submodule.clone(None).unwrap();
+        let commit = sub_repo.find_commit(pinned).unwrap();
+        sub_repo.checkout_tree(commit.as_object(), None).unwrap();
+        sub_repo.set_head_detached(pinned).unwrap();
+        submodule.add_to_index(true).unwrap();
+        submodule.add_finalize().unwrap();
+
+        drop(submodule);
+
+        let sig = git2::Signature::now("test", "test@example.com").unwrap();
+        {
+            let mut index = repo.index().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let parent = repo.head().unwrap().peel_to_commit().unwrap();
+            repo.commit(Some("HEAD"), &sig, &sig, "add submodule", &tree, &[&parent]).unwrap();
+        }
+        (repo, pinned)
+    }
+
+    #[test]
+    fn test_collect_tree_submodules_at_pinned_commit() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, pinned) = superproject(temp_dir.path());
+        let work_dir = temp_dir.path().join("work");
+
+        let options = ScanOptions::for_tests();
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        let (submodules, files) = collect_tree_submodules(
+            &repo,
+            &tree,
+            "",
+            &options,
+            &CloneOptions::default(),
+            &work_dir,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(submodules.len(), 1);
+        assert_eq!(submodules[0].path, "vendor/lib");
+        assert_eq!(submodules[0].commit, pinned.to_string());
+        let rels: Vec<_> = files.iter().map(|f| f.rel.as_str()).collect();
+        assert_eq!(rels, ["vendor/lib/inner/mod.rs", "vendor/lib/lib.rs"]);
+        assert_eq!(files[1].read_to_string().unwrap(), "v1");
+
+        // A subdir inside the submodule only reads that directory of it
+        let scoped = ScanOptions { subdir: Some("vendor/lib/inner".to_string()), ..options.clone() };
+        let (submodules, files) = collect_tree_submodules(
+            &repo,
+            &tree,
+            "",
+            &scoped,
+            &CloneOptions::default(),
+            &work_dir,
+            false,
+        )
+        .unwrap();
+        assert!(contains_subdir(&submodules, scoped.subdir.as_deref()));
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].rel, "vendor/lib/inner/mod.rs");
+    }
+
+    #[test]
+    fn test_worktree_submodules_leave_the_checkout_alone() {
+        let temp_dir = TempDir::new().unwrap();
+        let (repo, pinned) = superproject(temp_dir.path());
+        let options = ScanOptions::for_tests();
+        let work_dir = temp_dir.path().join("work");
+        let collect = |dir: &Path, options: &ScanOptions| {
+            collect_worktree_submodules(dir, "", options, &CloneOptions::default(), &work_dir, false).unwrap()
+        };
+
+        // Checked out: reported, files left to the working tree scan
+        let (submodules, files) = collect(repo.workdir().unwrap(), &options);
+        assert_eq!(submodules.len(), 1);
+        assert_eq!(submodules[0].commit, pinned.to_string());
+        assert!(files.is_empty());
+
+        // Not initialized: read from objects, and the checkout stays empty
+        let clone_dir = temp_dir.path().join("clone");
+        Repository::clone(repo.workdir().unwrap().to_str().unwrap(), &clone_dir).unwrap();
+        let (submodules, files) = collect(&clone_dir, &options);
+        assert_eq!(submodules[0].commit, pinned.to_string());
+        assert_eq!(files[1].rel, "vendor/lib/lib.rs");
+        assert_eq!(files[1].read_to_string().unwrap(), "v1");
+        assert!(!clone_dir.join("vendor/lib/lib.rs").exists());
+        assert!(!clone_dir.join(".git/modules").exists());
+
+        let scoped = ScanOptions { subdir: Some("src".to_string()), ..options.clone() };
+        assert!(collect(&clone_dir, &scoped).0.is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::file_analyzer::MARKDOWN_EXTENSIONS;
+
+/// How tokens are counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Tokenizer {
+    /// The o200k_base BPE (GPT-4o and later)
+    O200k,
+    /// The cl100k_base BPE (GPT-4, GPT-3.5)
+    Cl100k,
+    /// Bytes divided by four, no tokenizer
+    #[default]
+    Estimate,
+}
+
+impl Tokenizer {
+    pub fn count(self, text: &str) -> usize {
+        match self {
+            Tokenizer::O200k => tiktoken_rs::o200k_base_singleton().encode_ordinary(text).len(),
+            Tokenizer::Cl100k => tiktoken_rs::cl100k_base_singleton().encode_ordinary(text).len(),
+            Tokenizer::Estimate => estimate(text),
+        }
+    }
+
+    /// The tokenizer asked for, or else o200k when a budget has to be kept
+    /// and the cheap estimate when the counts are only shown
+    pub fn resolve(choice: Option<Tokenizer>, budget: Option<usize>) -> Tokenizer {
+        choice.unwrap_or(if budget.is_some() { Tokenizer::O200k } else { Tokenizer::Estimate })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tokenizer::O200k => "o200k_base",
+            Tokenizer::Cl100k => "cl100k_base",
+            Tokenizer::Estimate => "bytes/4 estimate",
+        }
+    }
+}
+
+/// Rough token count: one token per four bytes, rounded up
+pub fn estimate(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+/// Format a token count as e.g. `950`, `12.3k` or `1.4M`
+pub fn tokens_human(n: usize) -> String {
+    if n < 1000 {
+        return n.to_string();
+    }
+    // Round to tenths before picking the unit, so 999,950 is 1.0M, not 1000.0k
+    let tenths = (n + 50) / 100;
+    if tenths < 10_000 {
+        format!("{}.{}k", tenths / 10, tenths % 10)
+    } else {
+        let tenths = (n + 50_000) / 100_000;
+        format!("{}.{}M", tenths / 10, tenths % 10)
+    }
+}
+
+/// Order in which files are taken into the CXML document under `--token-budget`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum BudgetOrder {
+    /// Path order, as in the table of contents
+    #[default]
+    Path,
+    /// Smallest files first, to fit as many as possible
+    Smallest,
+    /// READMEs, then docs and build manifests, then the rest; shallower paths first
+    Overview,
+}
+
+/// Build and package manifests that describe a project at a glance
+const PROJECT_FILES: &[&str] = &[
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "setup.py",
+    "go.mod",
+    "pom.xml",
+    "build.gradle",
+    "build.gradle.kts",
+    "CMakeLists.txt",
+    "Makefile",
+    "Gemfile",
+    "composer.json",
+];
+
+impl BudgetOrder {
+    /// Indices of `files` (repo-relative path and token count, in path
+    /// order) from the first to the last to take
+    pub fn priority(self, files: &[(&str, usize)]) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..files.len()).collect();
+        match self {
+            BudgetOrder::Path => {}
+            BudgetOrder::Smallest => order.sort_by_key(|&i| files[i].1),
+            BudgetOrder::Overview => order.sort_by_key(|&i| {
+                let rel = files[i].0;
+                (overview_rank(rel), rel.matches('/').count())
+            }),
+        }
+        order
+    }
+}
+
+fn overview_rank(rel: &str) -> u8 {
+    let name = rel.rsplit('/').next().unwrap_or(rel);
+    let lower = name.to_lowercase();
+    if lower.starts_with("readme") {
+        0
+    } else if PROJECT_FILES.contains(&name)
+        || MARKDOWN_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+        || rel.starts_with("docs/")
+    {
+        1
+    } else {
+        2
+    }
+}
+
+/// How tokens are counted, and the budget of the CXML document
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenOptions {
+    pub tokenizer: Tokenizer,
+    /// Most tokens the CXML document may take; files that don't fit are listed instead
+    pub budget: Option<usize>,
+    pub order: BudgetOrder,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_and_format() {
+        assert_eq!(Tokenizer::O200k.count("hello world"), 2);
+        assert_eq!(Tokenizer::Estimate.count("hello world"), 3);
+        assert_eq!(tokens_human(950), "950");
+        assert_eq!(tokens_human(12_345), "12.3k");
+        assert_eq!(tokens_human(1_400_000), "1.4M");
+        assert_eq!(tokens_human(999_949), "999.9k");
+        assert_eq!(tokens_human(999_950), "1.0M");
+    }
+
+    #[test]
+    fn test_priority() {
+        let files = [("src/main.rs", 50), ("Cargo.toml", 30), ("README.md", 80), ("docs/guide.md", 10)];
+        assert_eq!(BudgetOrder::Path.priority(&files), vec![0, 1, 2, 3]);
+        assert_eq!(BudgetOrder::Smallest.priority(&files), vec![3, 1, 0, 2]);
+        assert_eq!(BudgetOrder::Overview.priority(&files), vec![2, 1, 3, 0]);
+    }
+}
@@ -1,107 +1,209 @@
-use anyhow::Result;
-use std::fs;
-use std::path::Path;
-use std::process::Command;
-
-/// Generate a tree-like directory structure
-pub fn generate_tree(root: &Path) -> Result<String> {
-    // Try to use the external 'tree' command first
-    if let Ok(output) = try_tree_command(root) {
-        return Ok(output);
-    }
+use crate::file_analyzer::FileInfo;
+use crate::submodules::SubmoduleInfo;
+use std::collections::BTreeMap;
+
+/// One line of the directory tree
+pub struct TreeLine<'a> {
+    /// Box-drawing prefix, e.g. `│   ├── ` (empty for the root)
+    pub prefix: String,
+    pub name: String,
+    pub entry: TreeEntry<'a>,
+}
 
-    // Fallback to our own implementation
-    Ok(generate_tree_fallback(root))
+/// What a tree line stands for
+pub enum TreeEntry<'a> {
+    Root,
+    Dir { submodule: Option<&'a SubmoduleInfo> },
+    File(&'a FileInfo),
 }
 
-/// Try to use the external 'tree' command
-fn try_tree_command(root: &Path) -> Result<String> {
-    let output = Command::new("tree")
-        .arg("-a")
-        .arg("-I")
-        .arg(".git")
-        .arg(".")
-        .current_dir(root)
-        .output()?;
-
-    if output.status.success() {
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
-    } else {
-        anyhow::bail!("tree command failed")
+impl TreeLine<'_> {
+    /// Size and render status shown after the name, e.g.
+    /// `(1.2 KiB, rendered)` or `(3.4 MiB, too large)`
+    pub fn annotation(&self) -> String {
+        match &self.entry {
+            TreeEntry::Root => String::new(),
+            TreeEntry::Dir { submodule } => submodule.map(|sm| sm.label()).unwrap_or_default(),
+            // Directories skipped as a whole are recorded with a trailing slash
+            TreeEntry::File(info) if info.rel.ends_with('/') => format!("({})", info.decision.status()),
+            TreeEntry::File(info) => match &info.symlink_target {
+                Some(target) => format!("(→ {}, {})", target, info.decision.status()),
+                None => format!("({}, {})", info.size_human(), info.decision.status()),
+            },
+        }
     }
 }
 
-/// Fallback tree generation when 'tree' command is not available
-fn generate_tree_fallback(root: &Path) -> String {
-    let mut lines = Vec::new();
-    
-    let root_name = root.file_name()
-        .map(|n| n.to_string_lossy().to_string())
-        .unwrap_or_else(|| "repo".to_string());
-    
-    lines.push(root_name);
-    walk_dir(root, "", &mut lines);
-    
-    lines.join("\n")
+/// A folder of the tree with its subfolders and files, both keyed by name
+/// so iteration order is the same on every platform
+#[derive(Default)]
+pub struct DirNode<'a> {
+    pub dirs: BTreeMap<String, DirNode<'a>>,
+    pub files: BTreeMap<String, &'a FileInfo>,
 }
 
-/// Recursively walk directory and build tree lines
-fn walk_dir(dir: &Path, prefix: &str, lines: &mut Vec<String>) {
-    let mut entries: Vec<_> = match fs::read_dir(dir) {
-        Ok(entries) => entries
-            .filter_map(|e| e.ok())
-            .filter(|e| {
-                // Skip .git directory
-                e.file_name() != ".git"
-            })
-            .collect(),
-        Err(_) => return,
-    };
-
-    // Sort: directories first, then by name
-    entries.sort_by(|a, b| {
-        let a_is_dir = a.path().is_dir();
-        let b_is_dir = b.path().is_dir();
-        
-        match (a_is_dir, b_is_dir) {
-            (true, false) => std::cmp::Ordering::Less,
-            (false, true) => std::cmp::Ordering::Greater,
-            _ => a.file_name().cmp(&b.file_name()),
+impl<'a> DirNode<'a> {
+    /// Arrange files by their repo-relative paths. Directories skipped as a
+    /// whole (recorded with a trailing slash) become files named `dir/`.
+    pub fn build(file_infos: impl IntoIterator<Item = &'a FileInfo>) -> Self {
+        let mut root = DirNode::default();
+        for info in file_infos {
+            let mut node = &mut root;
+            let mut parts: Vec<&str> = info.rel.split('/').filter(|p| !p.is_empty()).collect();
+            let Some(file) = parts.pop() else { continue };
+            for part in parts {
+                node = node.dirs.entry(part.to_string()).or_default();
+            }
+            let name = if info.rel.ends_with('/') { format!("{}/", file) } else { file.to_string() };
+            node.files.insert(name, info);
         }
-    });
-
-    for (i, entry) in entries.iter().enumerate() {
-        let is_last = i == entries.len() - 1;
-        let branch = if is_last { "└── " } else { "├── " };
-        let name = entry.file_name().to_string_lossy().to_string();
-        
-        lines.push(format!("{}{}{}", prefix, branch, name));
-        
-        if entry.path().is_dir() {
+        root
+    }
+
+    /// Number of files in this folder and below
+    pub fn file_count(&self) -> usize {
+        self.files.len() + self.dirs.values().map(DirNode::file_count).sum::<usize>()
+    }
+
+    /// Total size of the files in this folder and below
+    pub fn total_size(&self) -> u64 {
+        self.files.values().map(|f| f.size).sum::<u64>()
+            + self.dirs.values().map(DirNode::total_size).sum::<u64>()
+    }
+}
+
+/// Lay out the tree of `file_infos`, folders first and then files, each
+/// sorted by name so the result is the same on every platform. Submodule
+/// folders carry their URL and pinned commit.
+pub fn tree_lines<'a>(
+    root_name: &str,
+    file_infos: &'a [FileInfo],
+    submodules: &'a [SubmoduleInfo],
+) -> Vec<TreeLine<'a>> {
+    let root = DirNode::build(file_infos);
+
+    fn walk_node<'a>(
+        node: &DirNode<'a>,
+        rel: &str,
+        prefix: &str,
+        submodules: &'a [SubmoduleInfo],
+        lines: &mut Vec<TreeLine<'a>>,
+    ) {
+        let total = node.dirs.len() + node.files.len();
+
+        for (i, (name, child)) in node.dirs.iter().enumerate() {
+            let is_last = i == total - 1;
+            let branch = if is_last { "└── " } else { "├── " };
+            let child_rel = format!("{}{}", rel, name);
+            lines.push(TreeLine {
+                prefix: format!("{}{}", prefix, branch),
+                name: name.clone(),
+                entry: TreeEntry::Dir {
+                    submodule: submodules.iter().find(|sm| sm.path == child_rel),
+                },
+            });
             let extension = if is_last { "    " } else { "│   " };
-            walk_dir(&entry.path(), &format!("{}{}", prefix, extension), lines);
+            walk_node(
+                child,
+                &format!("{}/", child_rel),
+                &format!("{}{}", prefix, extension),
+                submodules,
+                lines,
+            );
+        }
+        for (i, (name, info)) in node.files.iter().enumerate() {
+            let is_last = node.dirs.len() + i == total - 1;
+            let branch = if is_last { "└── " } else { "├── " };
+            lines.push(TreeLine {
+                prefix: format!("{}{}", prefix, branch),
+                name: name.clone(),
+                entry: TreeEntry::File(info),
+            });
         }
     }
+
+    let mut lines = vec![TreeLine {
+        prefix: String::new(),
+        name: root_name.to_string(),
+        entry: TreeEntry::Root,
+    }];
+    walk_node(&root, "", "", submodules, &mut lines);
+    lines
+}
+
+/// The annotated tree as plain text
+pub fn generate_tree_from_files(
+    root_name: &str,
+    file_infos: &[FileInfo],
+    submodules: &[SubmoduleInfo],
+) -> String {
+    tree_lines(root_name, file_infos, submodules)
+        .iter()
+        .map(|line| {
+            let annotation = line.annotation();
+            if annotation.is_empty() {
+                format!("{}{}", line.prefix, line.name)
+            } else {
+                format!("{}{}  {}", line.prefix, line.name, annotation)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
-    use tempfile::TempDir;
+    use crate::file_analyzer::file;
 
     #[test]
-    fn test_generate_tree_fallback() {
-        let temp_dir = TempDir::new().unwrap();
-        let root = temp_dir.path();
-        
-        // Create some test structure
-        fs::create_dir(root.join("src")).unwrap();
-        fs::write(root.join("src/main.rs"), "").unwrap();
-        fs::write(root.join("README.md"), "").unwrap();
-        
-        let tree = generate_tree_fallback(root);
-        assert!(tree.contains("src"));
-        assert!(tree.contains("README.md"));
+    fn test_generate_tree_from_files() {
+        let files = vec![
+            file("src/main.rs", 2048, "ok"),
+            file("README.md", 10, "ok"),
+            file("src/util/mod.rs", 0, "ok"),
+            file("vendor/lib/a.c", 3, "ok"),
+        ];
+
+        let submodules = vec![SubmoduleInfo {
+            path: "vendor/lib".to_string(),
+            url: "https://example.com/lib.git".to_string(),
+            commit: "0123456789abcdef".to_string(),
+        }];
+
+        let tree = generate_tree_from_files("repo", &files, &submodules);
+        assert_eq!(
+            tree,
+            "repo\n├── src\n│   ├── util\n│   │   └── mod.rs  (0 B, rendered)\n│   └── main.rs  (2.0 KiB, rendered)\n├── vendor\n│   └── lib  \
+             📦 submodule: https://example.com/lib.git @ 01234567\n│       └── a.c  (3 B, rendered)\n└── README.md  (10 B, rendered)"
+        );
+    }
+
+    #[test]
+    fn test_dir_node_counts() {
+        let files = vec![file("src/a.rs", 100, "ok"), file("src/deep/b.rs", 50, "ok"), file("c.txt", 1, "ok")];
+        let root = DirNode::build(&files);
+        assert_eq!((root.file_count(), root.total_size()), (3, 151));
+        assert_eq!((root.dirs["src"].file_count(), root.dirs["src"].total_size()), (2, 150));
+    }
+
+    #[test]
+    fn test_tree_annotates_skip_status() {
+        let mut link = file("docs/link.md", 0, "symlink");
+        link.symlink_target = Some("../README.md".to_string());
+        let files = vec![
+            file("logo.png", 1536, "binary"),
+            file("data.json", 2 * 1024 * 1024, "too_large"),
+            file("target/", 0, "gitignored"),
+            file("b.rs", 1, "ok"),
+            link,
+        ];
+
+        let tree = generate_tree_from_files("repo", &files, &[]);
+        assert_eq!(
+            tree,
+            "repo\n├── docs\n│   └── link.md  (→ ../README.md, symlink)\n├── b.rs  (1 B, rendered)\n\
+             ├── data.json  (2.0 MiB, too large)\n├── logo.png  (1.5 KiB, binary)\n└── target/  (ignored)"
+        );
     }
 }
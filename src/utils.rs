@@ -18,18 +18,35 @@ pub fn bytes_human(n: u64) -> String {
     }
 }
 
-/// Derive repository name from URL or path
+/// First eight characters of a commit id or revision, for display. Counts
+/// characters, not bytes, so a non-ASCII revision cannot split a character.
+pub fn short_id(id: &str) -> String {
+    id.chars().take(8).collect()
+}
+
+/// Derive repository name from URL or path. Handles nested groups
+/// (`gitlab.com/group/sub/project`), GitLab page URLs (`.../project/-/tree/main`)
+/// and scp-style URLs (`git@host:project.git`).
 pub fn derive_repo_name(repo_url: &str) -> String {
-    let parts: Vec<&str> = repo_url.trim_end_matches('/').split('/').collect();
-    if parts.len() >= 2 {
-        let mut name = parts[parts.len() - 1];
-        if name.ends_with(".git") {
-            name = &name[..name.len() - 4];
-        }
-        name.to_string()
-    } else {
-        "repo".to_string()
+    let mut rest = repo_url.trim();
+    if let Some((_, after)) = rest.split_once("://") {
+        rest = after;
     }
+    if let Some((repo, _)) = rest.split_once("/-/") {
+        rest = repo;
+    }
+
+    let last = rest.trim_end_matches('/').trim_end_matches(".git").rsplit('/').next().unwrap_or("");
+    // A bare `host:8080` is named after the host, not the port
+    let name = match last.rsplit_once(':') {
+        Some((host, port)) if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => host,
+        Some((_, path)) => path,
+        None => last,
+    };
+    Some(name.rsplit('@').next().unwrap_or(name))
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| "repo".to_string())
 }
 
 /// Simple slugify for creating HTML anchors
@@ -46,6 +63,50 @@ pub fn slugify(path_str: &str) -> String {
         .collect()
 }
 
+/// Make names unique in place by giving repeats a numbered suffix
+/// (`app`, `app-2`, `app-3`), keeping the first occurrence as is
+pub fn dedupe_names(names: &mut [String]) {
+    let mut seen = std::collections::HashSet::new();
+    for name in names.iter_mut() {
+        let mut candidate = name.clone();
+        let mut n = 1;
+        while !seen.insert(candidate.clone()) {
+            n += 1;
+            candidate = format!("{}-{}", name, n);
+        }
+        *name = candidate;
+    }
+}
+
+/// Read a text file, or stdin when the path is `-`
+pub fn read_text_or_stdin(path: &std::path::Path) -> anyhow::Result<String> {
+    use anyhow::Context;
+    use std::io::Read;
+
+    if path == std::path::Path::new("-") {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .context("Failed to read from stdin")?;
+        return Ok(text);
+    }
+    std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Percent-encode a slash-separated relative path for use in a link
+pub fn url_escape_path(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                escaped.push(byte as char)
+            }
+            _ => escaped.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    escaped
+}
+
 /// HTML escape a string
 pub fn html_escape(s: &str) -> String {
     html_escape::encode_text(s).to_string()
@@ -63,10 +124,30 @@ mod tests {
         assert_eq!(bytes_human(1048576), "1.0 MiB");
     }
 
+    #[test]
+    fn test_short_id() {
+        assert_eq!(short_id("0123456789abcdef"), "01234567");
+        assert_eq!(short_id("abc"), "abc");
+        assert_eq!(short_id("ünïcödé-ref"), "ünïcödé-");
+    }
+
     #[test]
     fn test_derive_repo_name() {
         assert_eq!(derive_repo_name("https://github.com/owner/repo"), "repo");
         assert_eq!(derive_repo_name("https://github.com/owner/repo.git"), "repo");
+        assert_eq!(derive_repo_name("https://gitlab.com/group/sub/project/-/tree/main"), "project");
+        assert_eq!(derive_repo_name("git@gitlab.com:group/sub/project.git"), "project");
+        assert_eq!(derive_repo_name("git@example.com:project.git"), "project");
+        assert_eq!(derive_repo_name("ssh://git@host:8080"), "host");
+        assert_eq!(derive_repo_name("https://host:8080/"), "host");
+        assert_eq!(derive_repo_name("https://host:8080/team/1234"), "1234");
+    }
+
+    #[test]
+    fn test_dedupe_names() {
+        let mut names: Vec<String> = ["app", "app", "web", "app-2", "app"].iter().map(|s| s.to_string()).collect();
+        dedupe_names(&mut names);
+        assert_eq!(names, vec!["app", "app-2", "web", "app-2-2", "app-3"]);
     }
 
     #[test]
@@ -74,4 +155,11 @@ mod tests {
         assert_eq!(slugify("src/main.rs"), "src-main-rs");
         assert_eq!(slugify("README.md"), "README-md");
     }
+
+    #[test]
+    fn test_url_escape_path() {
+        assert_eq!(url_escape_path("files/src/main.rs.html"), "files/src/main.rs.html");
+        assert_eq!(url_escape_path("docs/a b#1?.md"), "docs/a%20b%231%3F.md");
+        assert_eq!(url_escape_path("é.txt"), "%C3%A9.txt");
+    }
 }